@@ -0,0 +1,323 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use hack_assembler::{
+    format::{self, Format},
+    instruction::{Dest, Jump},
+    ROM_SIZE,
+};
+
+/// Words of data memory: 16K of RAM, the 8K screen map and the keyboard.
+pub const RAM_SIZE: usize = 24577;
+/// First word of the memory-mapped screen.
+pub const SCREEN: u16 = 16384;
+/// The memory-mapped keyboard.
+pub const KBD: u16 = 24576;
+
+const C_INSTRUCTION: u16 = 0b1000000000000000;
+const A_BIT: u16 = 0b0001000000000000;
+const DEST_AND_JUMP: u16 = 0b111111;
+/// No dest and an unconditional jump.
+const JMP: u16 = 0b000111;
+
+/// Why `Emulator::run` returned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    /// The program reached the `@N 0;JMP` loop at address `N` that Hack
+    /// programs end with.
+    Halted,
+    /// `max_cycles` instructions ran without the program halting.
+    CycleLimit,
+}
+
+/// The Hack computer: a CPU with A, D and PC, 32K of ROM and the data
+/// memory. Every instruction takes one cycle.
+#[derive(Debug, Clone)]
+pub struct Emulator {
+    rom: Vec<u16>,
+    ram: Vec<u16>,
+    a: u16,
+    d: u16,
+    pc: u16,
+    cycles: u64,
+}
+
+impl Emulator {
+    /// Loads `program` at ROM[0]; the rest of the ROM holds `@0`.
+    pub fn new(program: &[u16]) -> Result<Self> {
+        if program.len() > ROM_SIZE {
+            return Err(anyhow!(
+                "program has {} words but the ROM holds {}",
+                program.len(),
+                ROM_SIZE
+            ));
+        }
+        let mut rom = vec![0; ROM_SIZE];
+        rom[..program.len()].copy_from_slice(program);
+        Ok(Emulator {
+            rom,
+            ram: vec![0; RAM_SIZE],
+            a: 0,
+            d: 0,
+            pc: 0,
+            cycles: 0,
+        })
+    }
+
+    /// Loads a `.hack` file, or any other format `hack_assembler` writes,
+    /// chosen by the extension.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let format = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(Format::from_extension)
+            .unwrap_or(Format::Hack);
+        let bytes = fs::read(path).with_context(|| format!("not find {}", path.display()))?;
+        let words =
+            format::decode(&bytes, format).with_context(|| format!("in {}", path.display()))?;
+        Emulator::new(&words)
+    }
+
+    /// Executes the instruction at PC. Like the CPU, it ignores bits 14 and
+    /// 13 of a C-instruction and computes any combination of the ALU control
+    /// bits, not only the 28 the assembler knows. Accessing M outside of the
+    /// data memory is an error and leaves the state as it was.
+    pub fn step(&mut self) -> Result<()> {
+        let word = self.rom[self.pc as usize];
+        if word & C_INSTRUCTION == 0 {
+            self.a = word;
+            self.pc = self.next_pc();
+            self.cycles += 1;
+            return Ok(());
+        }
+
+        let reads_m = word & A_BIT != 0;
+        let dest = Dest::from_bits((word >> 3) & 0b111).unwrap();
+        let jump = Jump::from_bits(word & 0b111).unwrap();
+        if (reads_m || dest.writes_m()) && self.a as usize >= RAM_SIZE {
+            return Err(anyhow!(
+                "at ROM[{}]: M is RAM[{}], outside of the data memory",
+                self.pc,
+                self.a
+            ));
+        }
+        let y = if reads_m {
+            self.ram[self.a as usize]
+        } else {
+            self.a
+        };
+        let value = alu(self.d, y, (word >> 6) & 0b111111);
+        // M and the jump target use A from before this instruction
+        let address = self.a;
+        if dest.writes_m() {
+            self.ram[address as usize] = value;
+        }
+        if dest.writes_a() {
+            self.a = value;
+        }
+        if dest.writes_d() {
+            self.d = value;
+        }
+        self.pc = if jump.is_taken(value) {
+            address & (ROM_SIZE as u16 - 1)
+        } else {
+            self.next_pc()
+        };
+        self.cycles += 1;
+        Ok(())
+    }
+
+    /// Steps until the program halts or `max_cycles` instructions have run.
+    pub fn run(&mut self, max_cycles: u64) -> Result<Stop> {
+        for _ in 0..max_cycles {
+            if self.is_halted() {
+                return Ok(Stop::Halted);
+            }
+            self.step()?;
+        }
+        Ok(if self.is_halted() {
+            Stop::Halted
+        } else {
+            Stop::CycleLimit
+        })
+    }
+
+    /// Whether PC is at an `@N 0;JMP` loop at address `N`, so the state can
+    /// no longer change.
+    pub fn is_halted(&self) -> bool {
+        let pc = self.pc as usize;
+        self.rom[pc] as usize == pc
+            && matches!(
+                self.rom.get(pc + 1),
+                Some(word) if word & C_INSTRUCTION != 0 && word & DEST_AND_JUMP == JMP
+            )
+    }
+
+    pub fn a(&self) -> u16 {
+        self.a
+    }
+
+    pub fn d(&self) -> u16 {
+        self.d
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Instructions executed so far.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// The data memory, including the screen map and the keyboard.
+    pub fn ram(&self) -> &[u16] {
+        &self.ram
+    }
+
+    /// Sets a memory word, e.g. an input in R0 or the key in KBD.
+    pub fn set_ram(&mut self, address: u16, value: u16) -> Result<()> {
+        let word = self
+            .ram
+            .get_mut(address as usize)
+            .ok_or_else(|| anyhow!("RAM[{}] is outside of the data memory", address))?;
+        *word = value;
+        Ok(())
+    }
+
+    /// Puts the CPU back at ROM[0] with A and D cleared, as the reset bit
+    /// does; memory keeps its contents.
+    pub fn reset(&mut self) {
+        self.a = 0;
+        self.d = 0;
+        self.pc = 0;
+        self.cycles = 0;
+    }
+
+    fn next_pc(&self) -> u16 {
+        (self.pc + 1) & (ROM_SIZE as u16 - 1)
+    }
+}
+
+/// The Hack ALU: `control` holds zx, nx, zy, ny, f and no from the most
+/// significant bit down.
+fn alu(x: u16, y: u16, control: u16) -> u16 {
+    let bit = |index: u16| control & (1 << (5 - index)) != 0;
+    let x = if bit(0) { 0 } else { x };
+    let x = if bit(1) { !x } else { x };
+    let y = if bit(2) { 0 } else { y };
+    let y = if bit(3) { !y } else { y };
+    let out = if bit(4) { x.wrapping_add(y) } else { x & y };
+    if bit(5) {
+        !out
+    } else {
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Emulator, Stop, KBD, SCREEN};
+    use hack_assembler::{
+        assemble,
+        instruction::{Comp, Dest, Instruction, Jump},
+    };
+    use std::path::Path;
+
+    fn load(source: &str) -> Emulator {
+        Emulator::new(&assemble(source).unwrap().words).unwrap()
+    }
+
+    #[test]
+    fn run_max_from_file() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../Max.hack");
+        for (r0, r1, max) in [(3, 5, 5), (9, 2, 9), (0xFFFF, 0, 0)].iter() {
+            let mut emulator = Emulator::from_file(&path).unwrap();
+            emulator.set_ram(0, *r0).unwrap();
+            emulator.set_ram(1, *r1).unwrap();
+            assert_eq!(emulator.run(1000).unwrap(), Stop::Halted);
+            assert_eq!(emulator.ram()[2], *max);
+        }
+    }
+
+    #[test]
+    fn run_mult() {
+        let mut emulator = load(include_str!("../../../04/mult/Mult.asm"));
+        emulator.set_ram(0, 6).unwrap();
+        emulator.set_ram(1, 7).unwrap();
+        assert_eq!(emulator.run(10_000).unwrap(), Stop::Halted);
+        assert_eq!(emulator.ram()[2], 42);
+    }
+
+    #[test]
+    fn write_m_and_jump_with_the_old_a() {
+        let mut emulator = load("@100\nD=A\n@7\nAM=D-1;JGT\n");
+        for _ in 0..4 {
+            emulator.step().unwrap();
+        }
+        assert_eq!((emulator.a(), emulator.d(), emulator.pc()), (99, 100, 7));
+        assert_eq!(emulator.ram()[7], 99);
+        assert_eq!(emulator.cycles(), 4);
+    }
+
+    #[test]
+    fn stop_at_the_cycle_limit_and_on_bad_memory() {
+        let mut emulator = load("(LOOP)\n@KBD\nD=M\n@SCREEN\nM=D\n@LOOP\n0;JMP\n");
+        emulator.set_ram(KBD, 65).unwrap();
+        assert_eq!(emulator.run(100).unwrap(), Stop::CycleLimit);
+        assert_eq!(emulator.ram()[SCREEN as usize], 65);
+
+        let mut emulator = load("@32767\nM=1\n");
+        emulator.step().unwrap();
+        assert_eq!(
+            emulator.step().unwrap_err().to_string(),
+            "at ROM[1]: M is RAM[32767], outside of the data memory"
+        );
+        assert_eq!(emulator.pc(), 1);
+        assert_eq!(
+            emulator.set_ram(KBD + 1, 0).unwrap_err().to_string(),
+            "RAM[24577] is outside of the data memory"
+        );
+    }
+
+    #[test]
+    fn execute_words_the_assembler_never_writes() {
+        let mut emulator = Emulator::new(&[
+            7,
+            // D=A with bits 14 and 13 clear
+            0b1000110000010000,
+            // zx nx zy ny f: D=(!0)+(!0), not a Hack mnemonic
+            0b1110111110010000,
+        ])
+        .unwrap();
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+        assert_eq!(emulator.d(), 7);
+        emulator.step().unwrap();
+        assert_eq!(emulator.d(), 0xFFFE);
+    }
+
+    #[test]
+    fn compute_every_mnemonic_like_the_assembler() {
+        for comp in Comp::all() {
+            let mut emulator = Emulator::new(&[Instruction::C {
+                dest: Dest::D,
+                comp,
+                jump: Jump::Null,
+            }
+            .encode()])
+            .unwrap();
+            emulator.a = 1234;
+            emulator.d = 0x8765;
+            emulator.set_ram(1234, 4321).unwrap();
+            emulator.step().unwrap();
+            let y = if comp.reads_m() { 4321 } else { 1234 };
+            assert_eq!(
+                emulator.d(),
+                comp.evaluate(0x8765, y),
+                "{}",
+                comp.mnemonic()
+            );
+        }
+    }
+}
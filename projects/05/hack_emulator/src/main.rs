@@ -0,0 +1,55 @@
+use std::{env, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use hack_emulator::{Emulator, Stop};
+
+const USAGE: &str = "usage: hack_emulator [--cycles=N] <file.hack> [ADDRESS=value]...
+
+Runs the program until it halts or N instructions (default 1000000) have run,
+then prints A, D, PC and RAM[0..16]. ADDRESS=value sets a RAM word first.";
+
+const DEFAULT_CYCLES: u64 = 1_000_000;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let mut max_cycles = DEFAULT_CYCLES;
+    let mut file_path = None;
+    let mut inputs = vec![];
+    for arg in args[1..].iter() {
+        if let Some(value) = arg.strip_prefix("--cycles=") {
+            max_cycles = value
+                .parse()
+                .with_context(|| format!("Invalid cycle count: {}", value))?;
+        } else if let Some((address, value)) = arg.split_once('=') {
+            let address: u16 = address
+                .parse()
+                .with_context(|| format!("Invalid RAM address: {}", address))?;
+            let value = value
+                .parse::<i16>()
+                .map(|value| value as u16)
+                .or_else(|_| value.parse::<u16>())
+                .with_context(|| format!("Invalid RAM value: {}", value))?;
+            inputs.push((address, value));
+        } else if file_path.is_none() && !arg.starts_with("--") {
+            file_path = Some(arg.clone());
+        } else {
+            return Err(anyhow!(USAGE));
+        }
+    }
+    let file_path = file_path.ok_or_else(|| anyhow!(USAGE))?;
+
+    let mut emulator = Emulator::from_file(Path::new(&file_path))?;
+    for (address, value) in inputs {
+        emulator.set_ram(address, value)?;
+    }
+    match emulator.run(max_cycles)? {
+        Stop::Halted => println!("halted after {} cycles", emulator.cycles()),
+        Stop::CycleLimit => println!("stopped after {} cycles", emulator.cycles()),
+    }
+    println!("A={} D={} PC={}", emulator.a(), emulator.d(), emulator.pc());
+    for (address, value) in emulator.ram()[..16].iter().enumerate() {
+        println!("RAM[{}] = {}", address, *value as i16);
+    }
+
+    Ok(())
+}
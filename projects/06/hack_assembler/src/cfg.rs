@@ -0,0 +1,260 @@
+use std::collections::{BTreeSet, HashSet};
+
+use crate::{disassembler::is_a_instruction, DefinitionKind, Program};
+
+/// A run of instructions entered only at the top and left only at the bottom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// ROM address of the first instruction.
+    pub start: usize,
+    /// ROM address after the last instruction.
+    pub end: usize,
+    /// Labels declared at `start`.
+    pub labels: Vec<String>,
+    /// Indices of the blocks control may pass to next.
+    pub successors: Vec<usize>,
+    /// Whether the block ends in a jump to an address computed at run time.
+    pub indirect: bool,
+    /// Whether control can run past the end of the block into nothing: off
+    /// the end of the program, or to an address outside of it.
+    pub falls_off: bool,
+    pub reachable: bool,
+}
+
+impl Block {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// A block with no successor never hands control on: it runs into
+    /// whatever lies past the program in ROM.
+    pub fn has_exit(&self) -> bool {
+        !self.successors.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct ControlFlowGraph {
+    /// In ROM order; the first block holds the entry point.
+    pub blocks: Vec<Block>,
+}
+
+const JUMP_BITS: u16 = 0b111;
+const DEST_A_BIT: u16 = 0b100000;
+const JMP: u16 = 0b111;
+
+impl ControlFlowGraph {
+    /// Splits `program` into blocks at every label and after every jump.
+    ///
+    /// A jump goes where the preceding `@` in the same block pointed. Jumps
+    /// through a computed A, such as returns in `vm_translator` output, may
+    /// reach any label whose address is loaded without being jumped to right
+    /// away.
+    pub fn new(program: &Program) -> Self {
+        let words = &program.words;
+        let labels: Vec<(usize, &str)> = program
+            .definitions
+            .iter()
+            .filter(|definition| definition.kind == DefinitionKind::Label)
+            .map(|definition| (definition.address as usize, definition.name.as_str()))
+            .collect();
+
+        let mut leaders: BTreeSet<usize> = labels
+            .iter()
+            .map(|(address, _)| *address)
+            .filter(|address| *address < words.len())
+            .collect();
+        leaders.insert(0);
+        for (address, word) in words.iter().enumerate() {
+            if !is_a_instruction(*word) && word & JUMP_BITS != 0 {
+                leaders.insert(address + 1);
+            }
+        }
+        leaders.retain(|address| *address < words.len());
+
+        let starts: Vec<usize> = leaders.into_iter().collect();
+        let block_at = |address: usize| starts.binary_search(&address).ok();
+
+        // labels whose address is used as data, possibly as a return address
+        let address_taken: HashSet<usize> = words
+            .iter()
+            .enumerate()
+            .filter(|(address, word)| {
+                is_a_instruction(**word)
+                    && !matches!(words.get(address + 1), Some(next) if !is_a_instruction(*next) && next & JUMP_BITS != 0)
+            })
+            .filter_map(|(_, word)| {
+                labels
+                    .iter()
+                    .find(|(address, _)| *address == *word as usize)
+                    .map(|(address, _)| *address)
+            })
+            .collect();
+
+        let mut blocks: Vec<Block> = starts
+            .iter()
+            .enumerate()
+            .map(|(index, start)| {
+                let end = starts.get(index + 1).copied().unwrap_or(words.len());
+                let mut block = Block {
+                    start: *start,
+                    end,
+                    labels: labels
+                        .iter()
+                        .filter(|(address, _)| address == start)
+                        .map(|(_, name)| name.to_string())
+                        .collect(),
+                    successors: vec![],
+                    indirect: false,
+                    falls_off: false,
+                    reachable: false,
+                };
+
+                let mut a_value: Option<u16> = None;
+                for word in words[*start..end - 1].iter() {
+                    if is_a_instruction(*word) {
+                        a_value = Some(*word);
+                    } else if word & DEST_A_BIT != 0 {
+                        a_value = None;
+                    }
+                }
+                let last = words[end - 1];
+                let jump = if is_a_instruction(last) {
+                    0
+                } else {
+                    last & JUMP_BITS
+                };
+
+                let mut successors = BTreeSet::new();
+                if jump != 0 {
+                    match a_value {
+                        Some(target) => match block_at(target as usize) {
+                            Some(target) => {
+                                successors.insert(target);
+                            }
+                            None => block.falls_off = true,
+                        },
+                        None => {
+                            block.indirect = true;
+                            successors.extend(
+                                address_taken
+                                    .iter()
+                                    .filter_map(|address| block_at(*address)),
+                            );
+                        }
+                    }
+                }
+                if jump != JMP {
+                    match block_at(end) {
+                        Some(next) => {
+                            successors.insert(next);
+                        }
+                        None => block.falls_off = true,
+                    }
+                }
+                block.successors = successors.into_iter().collect();
+                block
+            })
+            .collect();
+
+        let mut stack = if blocks.is_empty() { vec![] } else { vec![0] };
+        while let Some(index) = stack.pop() {
+            if blocks[index].reachable {
+                continue;
+            }
+            blocks[index].reachable = true;
+            stack.extend(blocks[index].successors.iter().copied());
+        }
+
+        ControlFlowGraph { blocks }
+    }
+
+    /// The graph in Graphviz DOT. Unreachable blocks are grey and dashed,
+    /// blocks with no exit red, and edges of computed jumps dashed.
+    pub fn dot(&self) -> String {
+        let mut text = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        for (index, block) in self.blocks.iter().enumerate() {
+            let name = if block.labels.is_empty() {
+                format!("ROM[{}]", block.start)
+            } else {
+                block.labels.join(", ")
+            };
+            // label names are symbols, so they need no escaping
+            let mut attributes = vec![format!(
+                "label=\"{}\\n{} words, ROM[{}..{}]\"",
+                name,
+                block.len(),
+                block.start,
+                block.end
+            )];
+            if !block.reachable {
+                attributes.push(String::from("style=\"dashed,filled\", fillcolor=lightgrey"));
+            }
+            if !block.has_exit() || block.falls_off {
+                attributes.push(String::from("color=red, penwidth=2"));
+            }
+            text.push_str(&format!("    b{} [{}];\n", index, attributes.join(", ")));
+        }
+        for (index, block) in self.blocks.iter().enumerate() {
+            for successor in block.successors.iter() {
+                let style = if block.indirect && self.blocks[*successor].start != block.end {
+                    " [style=dashed]"
+                } else {
+                    ""
+                };
+                text.push_str(&format!("    b{} -> b{}{};\n", index, successor, style));
+            }
+        }
+        text.push_str("}\n");
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ControlFlowGraph;
+    use crate::assemble;
+
+    #[test]
+    fn split_max_into_blocks() {
+        let program = assemble(include_str!("../../max/Max.asm")).unwrap();
+        let cfg = ControlFlowGraph::new(&program);
+        let shape: Vec<(usize, usize, Vec<usize>)> = cfg
+            .blocks
+            .iter()
+            .map(|block| (block.start, block.end, block.successors.clone()))
+            .collect();
+        assert_eq!(
+            shape,
+            vec![
+                (0, 6, vec![1, 2]),
+                (6, 10, vec![3]),
+                (10, 12, vec![3]),
+                (12, 14, vec![4]),
+                (14, 16, vec![4]),
+            ]
+        );
+        assert!(cfg
+            .blocks
+            .iter()
+            .all(|block| block.reachable && block.has_exit()));
+    }
+
+    #[test]
+    fn highlight_dead_and_stuck_blocks() {
+        let program = assemble("@END\n0;JMP\nD=1\n(END)\nD=0\n").unwrap();
+        let cfg = ControlFlowGraph::new(&program);
+        assert_eq!(cfg.blocks.len(), 3);
+        assert!(!cfg.blocks[1].reachable);
+        assert!(!cfg.blocks[2].has_exit());
+
+        let dot = cfg.dot();
+        assert!(dot.contains("b1 [label=\"ROM[2]\\n1 words, ROM[2..3]\", style=\"dashed,filled\", fillcolor=lightgrey];"));
+        assert!(dot.contains("b2 [label=\"END\\n1 words, ROM[3..4]\", color=red, penwidth=2];"));
+        assert!(dot.contains("b0 -> b2;"));
+    }
+}
@@ -1,59 +1,97 @@
-pub fn dest(mnemonic: &str) -> u16 {
-    match mnemonic {
-        "M" => 0b001,
-        "D" => 0b010,
-        "MD" => 0b011,
-        "A" => 0b100,
-        "AM" => 0b101,
-        "AD" => 0b110,
-        "AMD" => 0b111,
-        _ => 0b000,
-    }
+use crate::instruction::{Comp, Dest, Jump};
+
+/// Largest value an A-instruction can load; bit 15 would make it a C-instruction.
+pub const MAX_CONSTANT: u16 = 0b0111111111111111;
+
+/// Equivalent spellings of comp mnemonics and the canonical form they stand
+/// for: the operands of the commutative `+`, `&` and `|` may be swapped, so
+/// `A+D`, `M+D`, `A&D`, `M&D`, `A|D`, `M|D`, `1+D`, `1+A` and `1+M` are
+/// accepted as well.
+const COMP_ALTERNATES: [(&str, &str); 9] = [
+    ("A+D", "D+A"),
+    ("M+D", "D+M"),
+    ("A&D", "D&A"),
+    ("M&D", "D&M"),
+    ("A|D", "D|A"),
+    ("M|D", "D|M"),
+    ("1+D", "D+1"),
+    ("1+A", "A+1"),
+    ("1+M", "M+1"),
+];
+
+/// Decodes the numeric forms an A-instruction accepts: decimal (`16384`),
+/// hex (`0x4000`), binary (`0b1010`) and character (`'A'`) literals.
+///
+/// Returns `None` when `literal` is a symbol rather than a number.
+pub fn constant(literal: &str) -> Option<Result<u16, String>> {
+    let value = if let Some(hex) = literal.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = literal.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else if literal.starts_with('\'') {
+        let mut chars = literal.chars().skip(1);
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(c), Some('\''), None) => Some(c as u32),
+            _ => None,
+        }
+    } else if literal.starts_with(|c: char| c.is_ascii_digit()) {
+        literal.parse::<u32>().ok()
+    } else {
+        return None;
+    };
+
+    Some(match value {
+        Some(value) if value <= MAX_CONSTANT as u32 => Ok(value as u16),
+        None if literal.starts_with('\'') => {
+            Err(format!("invalid character literal `{}`", literal))
+        }
+        None if !literal.chars().all(|c| c.is_ascii_digit()) => {
+            Err(format!("invalid numeric constant `{}`", literal))
+        }
+        _ => Err(format!(
+            "constant `{}` does not fit in 15 bits (maximum is {})",
+            literal, MAX_CONSTANT
+        )),
+    })
 }
 
-pub fn jump(mnemonic: &str) -> u16 {
-    match mnemonic {
-        "JGT" => 0b001,
-        "JEQ" => 0b010,
-        "JGE" => 0b011,
-        "JLT" => 0b100,
-        "JNE" => 0b101,
-        "JLE" => 0b110,
-        "JMP" => 0b111,
-        _ => 0b000,
-    }
+pub fn dest(mnemonic: &str) -> Option<u16> {
+    Dest::from_mnemonic(mnemonic).map(Dest::bits)
+}
+
+pub fn jump(mnemonic: &str) -> Option<u16> {
+    Jump::from_mnemonic(mnemonic).map(Jump::bits)
 }
 
-pub fn comp(mnemonic: &str) -> u16 {
-    match mnemonic {
-        "0" => 0b0101010,
-        "1" => 0b0111111,
-        "-1" => 0b0111010,
-        "D" => 0b0001100,
-        "A" => 0b0110000,
-        "M" => 0b1110000,
-        "!D" => 0b0001101,
-        "!A" => 0b0110001,
-        "!M" => 0b1110001,
-        "-D" => 0b0001111,
-        "-A" => 0b0110011,
-        "-M" => 0b1110011,
-        "D+1" => 0b0011111,
-        "A+1" => 0b0110111,
-        "M+1" => 0b1110111,
-        "D-1" => 0b0001110,
-        "A-1" => 0b0110010,
-        "M-1" => 0b1110010,
-        "D+A" => 0b0000010,
-        "D+M" => 0b1000010,
-        "D-A" => 0b0010011,
-        "D-M" => 0b1010011,
-        "A-D" => 0b0000111,
-        "M-D" => 0b1000111,
-        "D&A" => 0b0000000,
-        "D&M" => 0b1000000,
-        "D|A" => 0b0010101,
-        "D|M" => 0b1010101,
-        _ => 0,
+pub fn comp(mnemonic: &str) -> Option<u16> {
+    Comp::from_mnemonic(mnemonic).map(Comp::bits)
+}
+
+/// The canonical spelling of a comp mnemonic, accepting the alternates in
+/// `COMP_ALTERNATES`. `None` when `mnemonic` is not a Hack operation at all.
+pub fn canonical_comp(mnemonic: &str) -> Option<&'static str> {
+    if let Some(comp) = Comp::from_mnemonic(mnemonic) {
+        return Some(comp.mnemonic());
     }
+
+    COMP_ALTERNATES
+        .iter()
+        .find(|(alternate, _)| *alternate == mnemonic)
+        .map(|(_, canonical)| *canonical)
+}
+
+/// Inverse of `dest`: the mnemonic encoded by the 3 dest bits.
+pub fn dest_mnemonic(bits: u16) -> Option<&'static str> {
+    Dest::from_bits(bits).map(Dest::mnemonic)
+}
+
+/// Inverse of `jump`: the mnemonic encoded by the 3 jump bits.
+pub fn jump_mnemonic(bits: u16) -> Option<&'static str> {
+    Jump::from_bits(bits).map(Jump::mnemonic)
+}
+
+/// Inverse of `comp`: the mnemonic encoded by the 7 comp bits (including `a`),
+/// or `None` when the bits are not a legal Hack operation.
+pub fn comp_mnemonic(bits: u16) -> Option<&'static str> {
+    Comp::from_bits(bits).map(Comp::mnemonic)
 }
@@ -0,0 +1,225 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    cfg::ControlFlowGraph,
+    diagnostic::{Diagnostic, Location},
+    instruction::Instruction,
+    Program,
+};
+
+/// An A-instruction that loads the address of a variable.
+#[derive(Debug, Clone)]
+pub(crate) struct VariableReference {
+    /// ROM address of the A-instruction.
+    pub(crate) address: usize,
+    pub(crate) name: String,
+    pub(crate) location: Location,
+    pub(crate) column: usize,
+}
+
+/// Warns about variables that may be read before they are written and about
+/// variables referenced only once, both usually misspellings.
+///
+/// A variable counts as written on a path once a C-instruction stores to `M`
+/// while A holds its address. The analysis runs forward over the reachable
+/// blocks of the control-flow graph, keeping the variables written on every
+/// path into a block. Stores through a computed address are not tracked.
+pub(crate) fn check_variables(
+    program: &Program,
+    references: &[VariableReference],
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let by_address: HashMap<usize, &VariableReference> = references
+        .iter()
+        .map(|reference| (reference.address, reference))
+        .collect();
+
+    let cfg = ControlFlowGraph::new(program);
+    let all: BTreeSet<&str> = references
+        .iter()
+        .map(|reference| reference.name.as_str())
+        .collect();
+    let mut predecessors: Vec<Vec<usize>> = vec![vec![]; cfg.blocks.len()];
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        for successor in block.successors.iter() {
+            predecessors[*successor].push(index);
+        }
+    }
+
+    // variables written on every path into each block, starting from "all"
+    // everywhere except the entry so loops do not hide their own writes
+    let mut written_in: Vec<BTreeSet<&str>> = vec![all.clone(); cfg.blocks.len()];
+    if let Some(entry) = written_in.first_mut() {
+        entry.clear();
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (index, block) in cfg.blocks.iter().enumerate() {
+            if index == 0 || !block.reachable {
+                continue;
+            }
+            let mut written: Option<BTreeSet<&str>> = None;
+            for predecessor in predecessors[index].iter() {
+                if !cfg.blocks[*predecessor].reachable {
+                    continue;
+                }
+                let out = transfer(
+                    program,
+                    &by_address,
+                    block_range(&cfg, *predecessor),
+                    &written_in[*predecessor],
+                    &mut vec![],
+                );
+                written = Some(match written {
+                    Some(written) => written.intersection(&out).copied().collect(),
+                    None => out,
+                });
+            }
+            let written = written.unwrap_or_default();
+            if written != written_in[index] {
+                written_in[index] = written;
+                changed = true;
+            }
+        }
+    }
+
+    let mut reported: BTreeSet<&str> = BTreeSet::new();
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        if !block.reachable {
+            continue;
+        }
+        let mut reads = vec![];
+        transfer(
+            program,
+            &by_address,
+            block_range(&cfg, index),
+            &written_in[index],
+            &mut reads,
+        );
+        for reference in reads {
+            if reported.insert(reference.name.as_str()) {
+                diagnostics.push(Diagnostic::warning(
+                    &reference.location,
+                    reference.column,
+                    format!(
+                        "variable `{}` may be read before it is written",
+                        reference.name
+                    ),
+                ));
+            }
+        }
+    }
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for reference in references.iter() {
+        *counts.entry(reference.name.as_str()).or_insert(0) += 1;
+    }
+    for reference in references.iter() {
+        if counts[reference.name.as_str()] == 1 {
+            diagnostics.push(Diagnostic::warning(
+                &reference.location,
+                reference.column,
+                format!(
+                    "variable `{}` is referenced only once; is it misspelled?",
+                    reference.name
+                ),
+            ));
+        }
+    }
+
+    diagnostics
+}
+
+fn block_range(cfg: &ControlFlowGraph, index: usize) -> std::ops::Range<usize> {
+    cfg.blocks[index].start..cfg.blocks[index].end
+}
+
+// the variables written after running `range`, collecting reads of
+// variables not yet written into `reads`
+fn transfer<'a>(
+    program: &Program,
+    by_address: &HashMap<usize, &'a VariableReference>,
+    range: std::ops::Range<usize>,
+    written_in: &BTreeSet<&'a str>,
+    reads: &mut Vec<&'a VariableReference>,
+) -> BTreeSet<&'a str> {
+    let mut written = written_in.clone();
+    let mut loaded: Option<&VariableReference> = None;
+    for address in range {
+        match Instruction::decode(program.words[address]) {
+            Ok(Instruction::A(_)) => loaded = by_address.get(&address).copied(),
+            Ok(Instruction::C { dest, comp, .. }) => {
+                if let Some(reference) = loaded {
+                    if comp.reads_m() && !written.contains(reference.name.as_str()) {
+                        reads.push(reference);
+                    }
+                    if dest.writes_m() {
+                        written.insert(reference.name.as_str());
+                    }
+                }
+                if dest.writes_a() {
+                    loaded = None;
+                }
+            }
+            _ => loaded = None,
+        }
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lint::lint;
+
+    #[test]
+    fn flag_read_before_write() {
+        let source = "\
+    @flag
+    D=M
+    @skip
+    D;JEQ
+    @count
+    M=1
+(skip)
+    @count
+    D=M
+    @flag
+    M=D
+(END)
+    @END
+    0;JMP
+";
+        assert_eq!(
+            lint(source),
+            vec![
+                "<input>:1:2: warning: variable `flag` may be read before it is written",
+                "<input>:8:6: warning: variable `count` may be read before it is written",
+            ]
+        );
+    }
+
+    #[test]
+    fn follow_writes_around_loops() {
+        let source = "\
+    @i
+    M=0
+(LOOP)
+    @i
+    MD=M+1
+    @LOOP
+    D;JGT
+";
+        assert!(lint(source).is_empty());
+    }
+
+    #[test]
+    fn flag_single_reference() {
+        assert_eq!(
+            lint("@total\nM=0\n@totl\nM=1\n@total\nD=M\n"),
+            vec![
+                "<input>:3:2: warning: variable `totl` is referenced only once; is it misspelled?"
+            ]
+        );
+    }
+}
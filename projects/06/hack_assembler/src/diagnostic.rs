@@ -0,0 +1,144 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    /// Extra context that is not tied to a source position.
+    Note,
+}
+
+/// A macro call or `.include` a source line was pulled in through.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion {
+    /// e.g. "in expansion of macro `PUSH_D`"
+    pub description: String,
+    pub file_name: String,
+    pub line: usize,
+}
+
+/// Where a line of assembly came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub file_name: String,
+    pub line: usize,
+    /// Innermost first, so the last entry is a line of the file being assembled.
+    pub expanded_from: Vec<Expansion>,
+}
+
+impl Location {
+    pub fn new(file_name: &str, line: usize) -> Self {
+        Self {
+            file_name: file_name.to_string(),
+            line,
+            expanded_from: vec![],
+        }
+    }
+
+    /// The line of the file being assembled this location ends up in.
+    pub fn root_line(&self) -> usize {
+        self.expanded_from
+            .last()
+            .map_or(self.line, |expansion| expansion.line)
+    }
+
+    /// This location seen from inside `expansion`, e.g. a macro body line
+    /// expanded at a call site.
+    pub fn expanded(&self, expansion: Expansion, outer: &Location) -> Self {
+        let mut expanded_from = vec![expansion];
+        expanded_from.extend(outer.expanded_from.iter().cloned());
+        Self {
+            file_name: self.file_name.clone(),
+            line: self.line,
+            expanded_from,
+        }
+    }
+}
+
+/// An error or warning tied to a position in an assembly source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub location: Location,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(location: &Location, column: usize, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            location: location.clone(),
+            column,
+            message,
+        }
+    }
+
+    pub fn warning(location: &Location, column: usize, message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::new(location, column, message)
+        }
+    }
+
+    pub fn note(message: String) -> Self {
+        Self {
+            severity: Severity::Note,
+            ..Self::new(&Location::new("", 0), 0, message)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => return write!(f, "note: {}", self.message),
+        };
+        write!(
+            f,
+            "{}:{}:{}: {}: {}",
+            self.location.file_name, self.location.line, self.column, severity, self.message
+        )?;
+        for expansion in self.location.expanded_from.iter() {
+            write!(
+                f,
+                "\n    {} at {}:{}",
+                expansion.description, expansion.file_name, expansion.line
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Every diagnostic collected while assembling a program that has at least one
+/// error, reported together.
+#[derive(Debug)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in self.0.iter() {
+            writeln!(f, "{}", diagnostic)?;
+        }
+        let count = |severity| {
+            self.0
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == severity)
+                .count()
+        };
+        write!(
+            f,
+            "{} error(s), {} warning(s)",
+            count(Severity::Error),
+            count(Severity::Warning)
+        )
+    }
+}
+
+impl std::error::Error for Diagnostics {}
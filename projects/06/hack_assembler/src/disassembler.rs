@@ -0,0 +1,141 @@
+use anyhow::{anyhow, Result};
+use std::collections::BTreeSet;
+
+use crate::instruction::Instruction;
+
+/// Readable assembly recovered from machine code.
+#[derive(Debug)]
+pub struct Disassembly {
+    pub text: String,
+    /// ROM addresses of words whose comp bits are not a legal Hack operation.
+    pub invalid_addresses: Vec<usize>,
+}
+
+/// Reads the ASCII `0`/`1` lines of a `.hack` file.
+pub fn parse_hack(text: &str) -> Result<Vec<u16>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let line = line.trim();
+            if line.len() != 16 {
+                return Err(anyhow!(
+                    "line {}: expected 16 bits, found `{}`",
+                    index + 1,
+                    line
+                ));
+            }
+            u16::from_str_radix(line, 2)
+                .map_err(|_| anyhow!("line {}: invalid binary word `{}`", index + 1, line))
+        })
+        .collect()
+}
+
+pub fn disassemble(words: &[u16]) -> Disassembly {
+    let targets = jump_targets(words);
+    let mut text = String::new();
+    let mut invalid_addresses = vec![];
+
+    for (address, word) in words.iter().enumerate() {
+        if targets.contains(&(address as u16)) {
+            text.push_str(&format!("({})\n", label(address as u16)));
+        }
+
+        if is_a_instruction(*word) {
+            if is_jump_address(words, address) && targets.contains(word) {
+                text.push_str(&format!("    @{}\n", label(*word)));
+            } else {
+                text.push_str(&format!("    @{}\n", word));
+            }
+            continue;
+        }
+
+        match c_instruction(*word) {
+            Some(instruction) => text.push_str(&format!("    {}\n", instruction)),
+            None => {
+                invalid_addresses.push(address);
+                text.push_str(&format!(
+                    "    // invalid: {:016b} (comp bits {:07b} are not a legal Hack operation)\n",
+                    word,
+                    comp_bits(*word)
+                ));
+            }
+        }
+    }
+
+    // a jump may target the address right after the last instruction
+    if targets.contains(&(words.len() as u16)) {
+        text.push_str(&format!("({})\n", label(words.len() as u16)));
+    }
+
+    Disassembly {
+        text,
+        invalid_addresses,
+    }
+}
+
+fn label(address: u16) -> String {
+    format!("L_{:04}", address)
+}
+
+pub(crate) fn is_a_instruction(word: u16) -> bool {
+    word & 0b1000000000000000 == 0
+}
+
+fn comp_bits(word: u16) -> u16 {
+    (word >> 6) & 0b1111111
+}
+
+// `dest=comp;jump` with the null fields left out, as they are written by
+// hand; like the CPU, this ignores bits 14 and 13
+fn c_instruction(word: u16) -> Option<String> {
+    Instruction::decode(word | 0b0110000000000000)
+        .ok()
+        .map(|instruction| instruction.to_string())
+}
+
+// an A-instruction whose value is consumed by the jump of the following C-instruction
+fn is_jump_address(words: &[u16], address: usize) -> bool {
+    match words.get(address + 1) {
+        Some(next) => !is_a_instruction(*next) && next & 0b111 != 0,
+        None => false,
+    }
+}
+
+fn jump_targets(words: &[u16]) -> BTreeSet<u16> {
+    words
+        .iter()
+        .enumerate()
+        .filter(|(address, word)| {
+            is_a_instruction(**word)
+                && is_jump_address(words, *address)
+                && **word as usize <= words.len()
+        })
+        .map(|(_, word)| *word)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, parse_hack};
+    use crate::assemble;
+
+    #[test]
+    fn disassemble_max() {
+        let words = parse_hack(include_str!("../../../05/Max.hack")).unwrap();
+        let disassembly = disassemble(&words);
+        assert!(disassembly.invalid_addresses.is_empty());
+        assert!(disassembly.text.contains("(L_0010)\n    @0\n"));
+        assert!(disassembly.text.contains("    @L_0010\n    D;JGT\n"));
+
+        // reassembling the output gives back the same machine code
+        assert_eq!(assemble(&disassembly.text).unwrap().words, words);
+    }
+
+    #[test]
+    fn flag_illegal_comp() {
+        let disassembly = disassemble(&[0b1110000001000000, 0b1110101010000111]);
+        assert_eq!(disassembly.invalid_addresses, vec![0]);
+        assert!(disassembly.text.contains("0;JMP"));
+    }
+}
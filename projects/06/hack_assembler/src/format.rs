@@ -0,0 +1,311 @@
+use anyhow::{anyhow, Context, Result};
+
+use crate::disassembler::parse_hack;
+
+/// The ways a program's words can be written to a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// One line of 16 ASCII `0`/`1` characters per word.
+    Hack,
+    /// Raw 16-bit words, big-endian.
+    Binary,
+    /// Intel HEX records of the big-endian bytes, two bytes per word.
+    IntelHex,
+    /// A Logisim `v2.0 raw` memory image.
+    Logisim,
+    /// `pub const PROGRAM: [u16; N] = [...];`
+    Rust,
+    /// `const unsigned short program[N] = {...};`
+    C,
+}
+
+/// Bytes of data per Intel HEX record.
+const HEX_RECORD_LENGTH: usize = 16;
+/// Words per line of the Logisim image and the array literals.
+const WORDS_PER_LINE: usize = 8;
+
+impl Format {
+    /// Parses the value of `--format`.
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "hack" => Some(Format::Hack),
+            "bin" => Some(Format::Binary),
+            "hex" => Some(Format::IntelHex),
+            "logisim" => Some(Format::Logisim),
+            "rust" => Some(Format::Rust),
+            "c" => Some(Format::C),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Hack => "hack",
+            Format::Binary => "bin",
+            Format::IntelHex => "hex",
+            Format::Logisim => "rom",
+            Format::Rust => "rs",
+            Format::C => "c",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Format> {
+        [
+            Format::Hack,
+            Format::Binary,
+            Format::IntelHex,
+            Format::Logisim,
+            Format::Rust,
+            Format::C,
+        ]
+        .iter()
+        .copied()
+        .find(|format| format.extension() == extension)
+    }
+}
+
+pub fn encode(words: &[u16], format: Format) -> Vec<u8> {
+    match format {
+        Format::Hack => words
+            .iter()
+            .map(|word| format!("{:016b}\n", word))
+            .collect::<String>()
+            .into_bytes(),
+        Format::Binary => words.iter().flat_map(|word| word.to_be_bytes()).collect(),
+        Format::IntelHex => intel_hex(words).into_bytes(),
+        Format::Logisim => logisim(words).into_bytes(),
+        Format::Rust => array(
+            words,
+            &format!("pub const PROGRAM: [u16; {}] = [", words.len()),
+            "];",
+        )
+        .into_bytes(),
+        Format::C => array(
+            words,
+            &format!("const unsigned short program[{}] = {{", words.len()),
+            "};",
+        )
+        .into_bytes(),
+    }
+}
+
+/// Reads back what `encode` wrote.
+pub fn decode(bytes: &[u8], format: Format) -> Result<Vec<u16>> {
+    if format == Format::Binary {
+        if !bytes.len().is_multiple_of(2) {
+            return Err(anyhow!("odd number of bytes in a 16-bit binary image"));
+        }
+        return Ok(bytes
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect());
+    }
+
+    let text = std::str::from_utf8(bytes).context("expected a text file")?;
+    match format {
+        Format::Hack => parse_hack(text),
+        Format::IntelHex => parse_intel_hex(text),
+        Format::Logisim => parse_logisim(text),
+        _ => parse_array(text),
+    }
+}
+
+fn intel_hex(words: &[u16]) -> String {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+    let mut text = String::new();
+    for (index, chunk) in bytes.chunks(HEX_RECORD_LENGTH).enumerate() {
+        text.push_str(&hex_record((index * HEX_RECORD_LENGTH) as u16, 0x00, chunk));
+    }
+    text.push_str(&hex_record(0, 0x01, &[]));
+    text
+}
+
+fn hex_record(address: u16, record_type: u8, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(address.to_be_bytes());
+    bytes.push(record_type);
+    bytes.extend(data);
+    let checksum = bytes
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg();
+    bytes.push(checksum);
+    let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!(":{}\n", hex)
+}
+
+fn parse_intel_hex(text: &str) -> Result<Vec<u16>> {
+    let mut bytes: Vec<u8> = vec![];
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = || anyhow!("line {}: malformed Intel HEX record `{}`", index + 1, line);
+        let digits = line.strip_prefix(':').ok_or_else(error)?;
+        if !digits.is_ascii() || digits.len() < 10 || !digits.len().is_multiple_of(2) {
+            return Err(error());
+        }
+        let record = (0..digits.len())
+            .step_by(2)
+            .map(|start| u8::from_str_radix(&digits[start..start + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| error())?;
+        if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(anyhow!("line {}: bad Intel HEX checksum", index + 1));
+        }
+        let length = record[0] as usize;
+        if record.len() != length + 5 {
+            return Err(error());
+        }
+        let address = u16::from_be_bytes([record[1], record[2]]) as usize;
+        match record[3] {
+            0x00 => {
+                if bytes.len() < address + length {
+                    bytes.resize(address + length, 0);
+                }
+                bytes[address..address + length].copy_from_slice(&record[4..4 + length]);
+            }
+            0x01 => break,
+            record_type => {
+                return Err(anyhow!(
+                    "line {}: unsupported Intel HEX record type {:02X}",
+                    index + 1,
+                    record_type
+                ))
+            }
+        }
+    }
+    decode(&bytes, Format::Binary)
+}
+
+// runs of equal words are written as `count*word`, as Logisim does
+fn logisim(words: &[u16]) -> String {
+    let mut entries: Vec<String> = vec![];
+    let mut index = 0;
+    while index < words.len() {
+        let run = words[index..]
+            .iter()
+            .take_while(|word| **word == words[index])
+            .count();
+        if run > 1 {
+            entries.push(format!("{}*{:x}", run, words[index]));
+        } else {
+            entries.push(format!("{:x}", words[index]));
+        }
+        index += run;
+    }
+
+    let mut text = String::from("v2.0 raw\n");
+    for line in entries.chunks(WORDS_PER_LINE) {
+        text.push_str(&line.join(" "));
+        text.push('\n');
+    }
+    text
+}
+
+fn parse_logisim(text: &str) -> Result<Vec<u16>> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("v2.0 raw") {
+        return Err(anyhow!("expected a Logisim `v2.0 raw` image"));
+    }
+
+    let mut words = vec![];
+    for entry in lines.flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace()) {
+        let error = || anyhow!("malformed Logisim entry `{}`", entry);
+        let (count, word) = match entry.split_once('*') {
+            Some((count, word)) => (count.parse::<usize>().map_err(|_| error())?, word),
+            None => (1, entry),
+        };
+        let word = u16::from_str_radix(word, 16).map_err(|_| error())?;
+        words.extend(std::iter::repeat_n(word, count));
+    }
+    Ok(words)
+}
+
+fn array(words: &[u16], opening: &str, closing: &str) -> String {
+    let mut text = format!("{}\n", opening);
+    for line in words.chunks(WORDS_PER_LINE) {
+        let literals: Vec<String> = line.iter().map(|word| format!("0x{:04X},", word)).collect();
+        text.push_str(&format!("    {}\n", literals.join(" ")));
+    }
+    text.push_str(closing);
+    text.push('\n');
+    text
+}
+
+// the `0x` literals between the brackets after the `=` of a Rust or C array
+fn parse_array(text: &str) -> Result<Vec<u16>> {
+    let equals = text.find('=').context("expected an array literal")?;
+    let body = &text[equals + 1..];
+    let open = body.find(['{', '[']).context("expected an array literal")?;
+    let close = body
+        .find(['}', ']'])
+        .context("unterminated array literal")?;
+
+    body[open + 1..close]
+        .split(',')
+        .map(str::trim)
+        .filter(|literal| !literal.is_empty())
+        .map(|literal| {
+            let digits = literal
+                .strip_prefix("0x")
+                .or_else(|| literal.strip_prefix("0X"))
+                .ok_or_else(|| anyhow!("expected a hex literal, found `{}`", literal))?;
+            u16::from_str_radix(digits, 16)
+                .map_err(|_| anyhow!("invalid hex literal `{}`", literal))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, Format};
+
+    #[test]
+    fn round_trip_every_format() {
+        let words = vec![0x0002, 0xEC10, 0x0003, 0xE090, 0, 0, 0, 0xE308, 0x7FFF];
+        for name in ["hack", "bin", "hex", "logisim", "rust", "c"].iter() {
+            let format = Format::from_name(name).unwrap();
+            let encoded = encode(&words, format);
+            assert_eq!(decode(&encoded, format).unwrap(), words, "{}", name);
+            assert_eq!(Format::from_extension(format.extension()), Some(format));
+        }
+    }
+
+    #[test]
+    fn write_known_formats() {
+        let words = [0x0002, 0xEC10];
+        assert_eq!(encode(&words, Format::Binary), vec![0x00, 0x02, 0xEC, 0x10]);
+        assert_eq!(
+            String::from_utf8(encode(&words, Format::IntelHex)).unwrap(),
+            ":040000000002EC10FE\n:00000001FF\n"
+        );
+        assert_eq!(
+            String::from_utf8(encode(&[0, 0, 0, 7], Format::Logisim)).unwrap(),
+            "v2.0 raw\n3*0 7\n"
+        );
+        assert_eq!(
+            String::from_utf8(encode(&words, Format::C)).unwrap(),
+            "const unsigned short program[2] = {\n    0x0002, 0xEC10,\n};\n"
+        );
+    }
+
+    #[test]
+    fn reject_corrupt_images() {
+        assert!(decode(&[0x00], Format::Binary).is_err());
+        assert_eq!(
+            decode(b":040000000002EC10FF\n", Format::IntelHex)
+                .unwrap_err()
+                .to_string(),
+            "line 1: bad Intel HEX checksum"
+        );
+        assert_eq!(
+            decode(":0000000\u{e9}F\n".as_bytes(), Format::IntelHex)
+                .unwrap_err()
+                .to_string(),
+            "line 1: malformed Intel HEX record `:0000000\u{e9}F`"
+        );
+        assert!(decode(b"v2.0 raw\nzz\n", Format::Logisim).is_err());
+    }
+}
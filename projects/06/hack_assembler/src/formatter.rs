@@ -0,0 +1,209 @@
+use crate::{
+    code,
+    lexer::{tokenize, TokenKind},
+    parser::{parse_line, Command},
+};
+
+/// Indentation of instructions; labels and directives are flush-left.
+const INDENT: &str = "    ";
+/// Spaces between the longest instruction of a run and its trailing comment.
+const COMMENT_GAP: usize = 2;
+
+/// One source line split into the code and its `//` comment.
+struct FormattedLine {
+    code: String,
+    comment: Option<String>,
+}
+
+/// Re-emits `source` in the canonical layout:
+///
+/// - labels and directives flush-left, instructions indented by four spaces;
+/// - C-instructions without spaces and with the canonical comp spelling;
+/// - trailing comments aligned within each run of lines between blank lines,
+///   with a space after `//`;
+/// - full-line comments kept, indented if they were indented before;
+/// - at most one blank line in a row and none at the end.
+///
+/// Lines that do not parse are kept as written, only trimmed, so the parser
+/// can report them when the file is assembled.
+pub fn format_source(source: &str) -> String {
+    let mut output = String::new();
+    let mut run: Vec<FormattedLine> = vec![];
+    let mut blank = false;
+
+    for (index, text) in source.lines().enumerate() {
+        let (code, comment) = split_comment(text);
+        if code.trim().is_empty() && comment.is_none() {
+            flush(&mut run, &mut output);
+            blank = !output.is_empty();
+            continue;
+        }
+        if blank {
+            output.push('\n');
+            blank = false;
+        }
+
+        if code.trim().is_empty() {
+            // full-line comments start a new alignment run
+            flush(&mut run, &mut output);
+            let indent = if text.starts_with(char::is_whitespace) {
+                INDENT
+            } else {
+                ""
+            };
+            output.push_str(&format!("{}{}\n", indent, comment.unwrap()));
+            continue;
+        }
+
+        run.push(FormattedLine {
+            code: format_code(code, index + 1),
+            comment,
+        });
+    }
+    flush(&mut run, &mut output);
+
+    output
+}
+
+fn flush(run: &mut Vec<FormattedLine>, output: &mut String) {
+    let width = run
+        .iter()
+        .filter(|line| line.comment.is_some())
+        .map(|line| line.code.chars().count())
+        .max()
+        .unwrap_or(0);
+    for line in run.drain(..) {
+        match line.comment {
+            Some(comment) => output.push_str(&format!(
+                "{:width$}{}\n",
+                line.code,
+                comment,
+                width = width + COMMENT_GAP
+            )),
+            None => output.push_str(&format!("{}\n", line.code)),
+        }
+    }
+}
+
+// the code before `//` and the normalized comment, if any
+fn split_comment(text: &str) -> (&str, Option<String>) {
+    let start = match comment_start(text) {
+        Some(start) => start,
+        None => return (text, None),
+    };
+    let body = text[start + 2..].trim_end();
+    let comment =
+        if body.is_empty() || body.starts_with(char::is_whitespace) || body.starts_with('/') {
+            format!("//{}", body)
+        } else {
+            format!("// {}", body)
+        };
+    (&text[..start], Some(comment))
+}
+
+// `//` outside of character and string literals
+fn comment_start(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut quote: Option<u8> = None;
+    for (index, byte) in bytes.iter().enumerate() {
+        match quote {
+            Some(open) if *byte == open => quote = None,
+            Some(_) => {}
+            None if *byte == b'\'' || *byte == b'"' => quote = Some(*byte),
+            None if *byte == b'/' && bytes.get(index + 1) == Some(&b'/') => return Some(index),
+            None => {}
+        }
+    }
+    None
+}
+
+fn format_code(code: &str, line_number: usize) -> String {
+    let trimmed = code.trim();
+    let tokens = match tokenize(trimmed, line_number) {
+        Ok(tokens) => tokens,
+        Err(_) => return format!("{}{}", INDENT, trimmed),
+    };
+
+    // directives keep their arguments as written
+    if matches!(tokens.first(), Some(first) if first.kind == TokenKind::Symbol && first.text.starts_with('.'))
+    {
+        return trimmed.to_string();
+    }
+
+    match parse_line(trimmed, line_number) {
+        Ok(Some(Command::L(label))) => format!("({})", label.text),
+        Ok(Some(Command::A(value))) => format!("{}@{}", INDENT, value.text),
+        Ok(Some(Command::C { dest, comp, jump })) => {
+            let mut instruction = String::from(INDENT);
+            if let Some(dest) = dest {
+                instruction.push_str(&dest.text);
+                instruction.push('=');
+            }
+            instruction.push_str(code::canonical_comp(&comp.text).unwrap_or(&comp.text));
+            // `M=0;` assembles as no jump, so the empty jump is dropped
+            if let Some(jump) = jump.filter(|jump| !jump.text.is_empty()) {
+                instruction.push(';');
+                instruction.push_str(&jump.text);
+            }
+            instruction
+        }
+        // macro calls and malformed lines
+        _ => format!("{}{}", INDENT, trimmed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_source;
+    use crate::assemble;
+
+    #[test]
+    fn format_mixed_styles() {
+        let source = "\
+// header
+  (LOOP)
+@i // load i
+      D = M+D//add
+\tM=1+M ;  JGT
+
+
+   // indented note
+.equ N 4
+0;JMP
+";
+        assert_eq!(
+            format_source(source),
+            "\
+// header
+(LOOP)
+    @i     // load i
+    D=D+M  // add
+    M=M+1;JGT
+
+    // indented note
+.equ N 4
+    0;JMP
+"
+        );
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        for source in [
+            include_str!("../../max/Max.asm"),
+            include_str!("../../pong/Pong.asm"),
+            include_str!("../../../04/fill/Fill.asm"),
+        ]
+        .iter()
+        {
+            let formatted = format_source(source);
+            assert_eq!(format_source(&formatted), formatted);
+            assert_eq!(
+                assemble(&formatted).unwrap().words,
+                assemble(source).unwrap().words
+            );
+        }
+        assert!(format_source(include_str!("../../max/Max.asm")).contains("    D;JGT  // if D>0"));
+        assert_eq!(format_source("M = 0;\n"), "    M=0\n");
+    }
+}
@@ -0,0 +1,403 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+
+/// Bits 15, 14 and 13 of every C-instruction.
+const C_PREFIX: u16 = 0b1110000000000000;
+
+/// Where a C-instruction stores the ALU output. The discriminant is the 3
+/// dest bits.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dest {
+    Null,
+    M,
+    D,
+    MD,
+    A,
+    AM,
+    AD,
+    AMD,
+}
+
+impl Dest {
+    pub const ALL: [Dest; 8] = [
+        Dest::Null,
+        Dest::M,
+        Dest::D,
+        Dest::MD,
+        Dest::A,
+        Dest::AM,
+        Dest::AD,
+        Dest::AMD,
+    ];
+
+    pub fn bits(self) -> u16 {
+        self as u16
+    }
+
+    pub fn from_bits(bits: u16) -> Option<Dest> {
+        Dest::ALL.get(bits as usize).copied()
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        ["null", "M", "D", "MD", "A", "AM", "AD", "AMD"][self as usize]
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Dest> {
+        Dest::ALL
+            .iter()
+            .copied()
+            .find(|dest| dest.mnemonic() == mnemonic)
+    }
+
+    pub fn writes_a(self) -> bool {
+        self.bits() & 0b100 != 0
+    }
+
+    pub fn writes_d(self) -> bool {
+        self.bits() & 0b010 != 0
+    }
+
+    pub fn writes_m(self) -> bool {
+        self.bits() & 0b001 != 0
+    }
+}
+
+/// The condition under which a C-instruction jumps to A. The discriminant is
+/// the 3 jump bits.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jump {
+    Null,
+    JGT,
+    JEQ,
+    JGE,
+    JLT,
+    JNE,
+    JLE,
+    JMP,
+}
+
+impl Jump {
+    pub const ALL: [Jump; 8] = [
+        Jump::Null,
+        Jump::JGT,
+        Jump::JEQ,
+        Jump::JGE,
+        Jump::JLT,
+        Jump::JNE,
+        Jump::JLE,
+        Jump::JMP,
+    ];
+
+    pub fn bits(self) -> u16 {
+        self as u16
+    }
+
+    pub fn from_bits(bits: u16) -> Option<Jump> {
+        Jump::ALL.get(bits as usize).copied()
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        ["null", "JGT", "JEQ", "JGE", "JLT", "JNE", "JLE", "JMP"][self as usize]
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Jump> {
+        Jump::ALL
+            .iter()
+            .copied()
+            .find(|jump| jump.mnemonic() == mnemonic)
+    }
+
+    /// Whether the jump is taken for the ALU output `value`.
+    pub fn is_taken(self, value: u16) -> bool {
+        let value = value as i16;
+        (self.bits() & 0b100 != 0 && value < 0)
+            || (self.bits() & 0b010 != 0 && value == 0)
+            || (self.bits() & 0b001 != 0 && value > 0)
+    }
+}
+
+/// The ALU operation of a C-instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comp {
+    Zero,
+    One,
+    MinusOne,
+    D,
+    A,
+    M,
+    NotD,
+    NotA,
+    NotM,
+    NegD,
+    NegA,
+    NegM,
+    DPlusOne,
+    APlusOne,
+    MPlusOne,
+    DMinusOne,
+    AMinusOne,
+    MMinusOne,
+    DPlusA,
+    DPlusM,
+    DMinusA,
+    DMinusM,
+    AMinusD,
+    MMinusD,
+    DAndA,
+    DAndM,
+    DOrA,
+    DOrM,
+}
+
+/// Every operation with its canonical mnemonic and its 7 comp bits,
+/// including the `a` bit that selects M instead of A.
+const COMP_TABLE: [(Comp, &str, u16); 28] = [
+    (Comp::Zero, "0", 0b0101010),
+    (Comp::One, "1", 0b0111111),
+    (Comp::MinusOne, "-1", 0b0111010),
+    (Comp::D, "D", 0b0001100),
+    (Comp::A, "A", 0b0110000),
+    (Comp::M, "M", 0b1110000),
+    (Comp::NotD, "!D", 0b0001101),
+    (Comp::NotA, "!A", 0b0110001),
+    (Comp::NotM, "!M", 0b1110001),
+    (Comp::NegD, "-D", 0b0001111),
+    (Comp::NegA, "-A", 0b0110011),
+    (Comp::NegM, "-M", 0b1110011),
+    (Comp::DPlusOne, "D+1", 0b0011111),
+    (Comp::APlusOne, "A+1", 0b0110111),
+    (Comp::MPlusOne, "M+1", 0b1110111),
+    (Comp::DMinusOne, "D-1", 0b0001110),
+    (Comp::AMinusOne, "A-1", 0b0110010),
+    (Comp::MMinusOne, "M-1", 0b1110010),
+    (Comp::DPlusA, "D+A", 0b0000010),
+    (Comp::DPlusM, "D+M", 0b1000010),
+    (Comp::DMinusA, "D-A", 0b0010011),
+    (Comp::DMinusM, "D-M", 0b1010011),
+    (Comp::AMinusD, "A-D", 0b0000111),
+    (Comp::MMinusD, "M-D", 0b1000111),
+    (Comp::DAndA, "D&A", 0b0000000),
+    (Comp::DAndM, "D&M", 0b1000000),
+    (Comp::DOrA, "D|A", 0b0010101),
+    (Comp::DOrM, "D|M", 0b1010101),
+];
+
+impl Comp {
+    pub fn all() -> impl Iterator<Item = Comp> {
+        COMP_TABLE.iter().map(|(comp, _, _)| *comp)
+    }
+
+    pub fn bits(self) -> u16 {
+        COMP_TABLE[self as usize].2
+    }
+
+    pub fn from_bits(bits: u16) -> Option<Comp> {
+        Comp::all().find(|comp| comp.bits() == bits)
+    }
+
+    /// The canonical spelling, e.g. `D+A` rather than `A+D`.
+    pub fn mnemonic(self) -> &'static str {
+        COMP_TABLE[self as usize].1
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Comp> {
+        Comp::all().find(|comp| comp.mnemonic() == mnemonic)
+    }
+
+    /// Whether the operation reads M rather than A.
+    pub fn reads_m(self) -> bool {
+        self.bits() & 0b1000000 != 0
+    }
+
+    /// What the ALU computes from D and A or M, whichever `reads_m` selects.
+    pub fn evaluate(self, d: u16, a_or_m: u16) -> u16 {
+        let y = a_or_m;
+        match self {
+            Comp::Zero => 0,
+            Comp::One => 1,
+            Comp::MinusOne => 0xFFFF,
+            Comp::D => d,
+            Comp::A | Comp::M => y,
+            Comp::NotD => !d,
+            Comp::NotA | Comp::NotM => !y,
+            Comp::NegD => d.wrapping_neg(),
+            Comp::NegA | Comp::NegM => y.wrapping_neg(),
+            Comp::DPlusOne => d.wrapping_add(1),
+            Comp::APlusOne | Comp::MPlusOne => y.wrapping_add(1),
+            Comp::DMinusOne => d.wrapping_sub(1),
+            Comp::AMinusOne | Comp::MMinusOne => y.wrapping_sub(1),
+            Comp::DPlusA | Comp::DPlusM => d.wrapping_add(y),
+            Comp::DMinusA | Comp::DMinusM => d.wrapping_sub(y),
+            Comp::AMinusD | Comp::MMinusD => y.wrapping_sub(d),
+            Comp::DAndA | Comp::DAndM => d & y,
+            Comp::DOrA | Comp::DOrM => d | y,
+        }
+    }
+}
+
+/// What an A-instruction loads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Value(u16),
+    /// A label, variable or constant not yet resolved to a value.
+    Symbol(String),
+}
+
+/// One line of Hack assembly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    A(Address),
+    C {
+        dest: Dest,
+        comp: Comp,
+        jump: Jump,
+    },
+    /// A label declaration, which takes no ROM.
+    Label(String),
+}
+
+impl Instruction {
+    /// The machine word.
+    ///
+    /// # Panics
+    ///
+    /// On a symbolic A-instruction or a label, which have no machine code
+    /// until the symbol table resolves them.
+    pub fn encode(&self) -> u16 {
+        match self {
+            Instruction::A(Address::Value(value)) => *value,
+            Instruction::C { dest, comp, jump } => {
+                C_PREFIX | comp.bits() << 6 | dest.bits() << 3 | jump.bits()
+            }
+            Instruction::A(Address::Symbol(_)) | Instruction::Label(_) => {
+                panic!("encode() called on an unresolved `{}`", self)
+            }
+        }
+    }
+
+    /// The instruction a machine word holds. C-instructions must have bits
+    /// 14 and 13 set and a comp that is a Hack operation, so that decoding
+    /// and encoding again gives back the same word.
+    pub fn decode(word: u16) -> Result<Instruction> {
+        if word & 0b1000000000000000 == 0 {
+            return Ok(Instruction::A(Address::Value(word)));
+        }
+        if word & C_PREFIX != C_PREFIX {
+            return Err(anyhow!(
+                "{:016b} is not a legal Hack instruction: bits 14 and 13 of a C-instruction must be set",
+                word
+            ));
+        }
+        let comp_bits = (word >> 6) & 0b1111111;
+        let comp = Comp::from_bits(comp_bits).ok_or_else(|| {
+            anyhow!(
+                "{:016b} is not a legal Hack instruction: comp bits {:07b} are not a Hack operation",
+                word,
+                comp_bits
+            )
+        })?;
+        Ok(Instruction::C {
+            dest: Dest::from_bits((word >> 3) & 0b111).unwrap(),
+            comp,
+            jump: Jump::from_bits(word & 0b111).unwrap(),
+        })
+    }
+}
+
+/// Assembly as it is written by hand: `@value`, `(label)`, or
+/// `dest=comp;jump` with the null fields left out.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::A(Address::Value(value)) => write!(f, "@{}", value),
+            Instruction::A(Address::Symbol(symbol)) => write!(f, "@{}", symbol),
+            Instruction::Label(label) => write!(f, "({})", label),
+            Instruction::C { dest, comp, jump } => {
+                if *dest != Dest::Null {
+                    write!(f, "{}=", dest.mnemonic())?;
+                }
+                write!(f, "{}", comp.mnemonic())?;
+                if *jump != Jump::Null {
+                    write!(f, ";{}", jump.mnemonic())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Address, Comp, Dest, Instruction, Jump};
+    use crate::assemble;
+
+    // there is no property-testing crate here, but 16 bits are few enough
+    // to check every word
+    #[test]
+    fn round_trip_every_word() {
+        let mut legal = 0;
+        for word in 0..=u16::MAX {
+            if let Ok(instruction) = Instruction::decode(word) {
+                assert_eq!(instruction.encode(), word, "{}", instruction);
+                legal += 1;
+            }
+        }
+        assert_eq!(legal, 32768 + 28 * 8 * 8);
+    }
+
+    #[test]
+    fn display_assembles_to_the_same_word() {
+        let mut instructions = vec![
+            Instruction::A(Address::Value(0)),
+            Instruction::A(Address::Value(0x7FFF)),
+        ];
+        for comp in Comp::all() {
+            for dest in Dest::ALL.iter() {
+                for jump in Jump::ALL.iter() {
+                    instructions.push(Instruction::C {
+                        dest: *dest,
+                        comp,
+                        jump: *jump,
+                    });
+                }
+            }
+        }
+
+        let source: String = instructions
+            .iter()
+            .map(|instruction| format!("{}\n", instruction))
+            .collect();
+        let words: Vec<u16> = instructions.iter().map(Instruction::encode).collect();
+        assert_eq!(assemble(&source).unwrap().words, words);
+        for (instruction, word) in instructions.iter().zip(words) {
+            assert_eq!(&Instruction::decode(word).unwrap(), instruction);
+        }
+    }
+
+    #[test]
+    fn reject_illegal_words() {
+        assert_eq!(
+            Instruction::decode(0b1000110000010000)
+                .unwrap_err()
+                .to_string(),
+            "1000110000010000 is not a legal Hack instruction: bits 14 and 13 of a C-instruction must be set"
+        );
+        assert!(Instruction::decode(0b1111111111000000).is_err());
+        assert_eq!(
+            Instruction::Label(String::from("LOOP")).to_string(),
+            "(LOOP)"
+        );
+    }
+
+    #[test]
+    fn evaluate_like_the_alu() {
+        assert_eq!(Comp::DMinusM.evaluate(3, 5), 0xFFFE);
+        assert_eq!(Comp::NotD.evaluate(0, 9), 0xFFFF);
+        assert!(Jump::JLE.is_taken(0xFFFE));
+        assert!(!Jump::JGT.is_taken(0x8000));
+        assert!(Dest::AMD.writes_a() && !Dest::MD.writes_a());
+    }
+}
@@ -0,0 +1,216 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    /// `@`
+    At,
+    /// `(`
+    LParen,
+    /// `)`
+    RParen,
+    /// `=`
+    Equals,
+    /// `;`
+    Semicolon,
+    /// `,` between macro arguments
+    Comma,
+    /// One of `== != < <= > >=` in a `.if` condition.
+    Comparison,
+    /// One of `+ - ! & |`.
+    Operator,
+    /// Letters, digits, `_`, `.`, `$` and `:`, not starting with a digit.
+    Symbol,
+    /// Decimal, `0x` hex or `0b` binary digits.
+    Number,
+    /// A quoted character such as `'A'`.
+    Char,
+    /// A double-quoted file name, quotes included.
+    String,
+}
+
+/// 1-based column range `[start, end)` of a token within its source line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub span: Span,
+}
+
+/// A line that does not follow the Hack assembly grammar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+pub fn is_symbol_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || "_.$:".contains(c)
+}
+
+pub fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_.$:".contains(c)
+}
+
+/// Splits one source line into tokens, dropping whitespace and any `//` comment.
+pub fn tokenize(line: &str, line_number: usize) -> Result<Vec<Token>, SyntaxError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = vec![];
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+        let start = index;
+
+        let kind = match c {
+            c if c.is_whitespace() => {
+                index += 1;
+                continue;
+            }
+            '/' if chars.get(index + 1) == Some(&'/') => break,
+            '@' => TokenKind::At,
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            '=' | '!' if chars.get(index + 1) == Some(&'=') => {
+                index += 1;
+                TokenKind::Comparison
+            }
+            '<' | '>' => {
+                if chars.get(index + 1) == Some(&'=') {
+                    index += 1;
+                }
+                TokenKind::Comparison
+            }
+            '=' => TokenKind::Equals,
+            ';' => TokenKind::Semicolon,
+            ',' => TokenKind::Comma,
+            '+' | '-' | '!' | '&' | '|' => TokenKind::Operator,
+            '\'' => {
+                if chars.get(index + 2) != Some(&'\'') {
+                    return Err(SyntaxError {
+                        line: line_number,
+                        column: start + 1,
+                        message: String::from("unterminated character literal"),
+                    });
+                }
+                index += 2;
+                TokenKind::Char
+            }
+            '"' => {
+                match chars[index + 1..].iter().position(|c| *c == '"') {
+                    Some(length) => index += length + 1,
+                    None => {
+                        return Err(SyntaxError {
+                            line: line_number,
+                            column: start + 1,
+                            message: String::from("unterminated string"),
+                        })
+                    }
+                }
+                TokenKind::String
+            }
+            c if c.is_ascii_digit() => {
+                while index + 1 < chars.len() && is_symbol_char(chars[index + 1]) {
+                    index += 1;
+                }
+                let text: String = chars[start..=index].iter().collect();
+                let is_number = text.starts_with("0x")
+                    || text.starts_with("0b")
+                    || text.chars().all(|c| c.is_ascii_digit());
+                if !is_number {
+                    return Err(SyntaxError {
+                        line: line_number,
+                        column: start + 1,
+                        message: format!("symbol `{}` cannot start with a digit", text),
+                    });
+                }
+                TokenKind::Number
+            }
+            c if is_symbol_start(c) => {
+                while index + 1 < chars.len() && is_symbol_char(chars[index + 1]) {
+                    index += 1;
+                }
+                TokenKind::Symbol
+            }
+            c => {
+                return Err(SyntaxError {
+                    line: line_number,
+                    column: start + 1,
+                    message: format!("unexpected character `{}`", c),
+                })
+            }
+        };
+
+        index += 1;
+        tokens.push(Token {
+            kind,
+            text: chars[start..index].iter().collect(),
+            span: Span {
+                start: start + 1,
+                end: index + 1,
+            },
+        });
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize, TokenKind};
+
+    fn kinds(line: &str) -> Vec<TokenKind> {
+        tokenize(line, 1)
+            .unwrap()
+            .iter()
+            .map(|token| token.kind)
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_instructions() {
+        use TokenKind::*;
+        assert_eq!(kinds("   @R0   // comment"), vec![At, Symbol]);
+        assert_eq!(kinds("(sys.init$loop)"), vec![LParen, Symbol, RParen]);
+        assert_eq!(
+            kinds("AM = M - 1 ; JNE"),
+            vec![Symbol, Equals, Symbol, Operator, Number, Semicolon, Symbol]
+        );
+        assert_eq!(kinds("@' '"), vec![At, Char]);
+        assert_eq!(kinds("@0x4000"), vec![At, Number]);
+        assert_eq!(kinds(".include \"a b.asm\""), vec![Symbol, String]);
+        assert_eq!(kinds(".if A<=B"), vec![Symbol, Symbol, Comparison, Symbol]);
+        assert_eq!(kinds("M=!M"), vec![Symbol, Equals, Operator, Symbol]);
+
+        let tokens = tokenize("  D=D+A", 1).unwrap();
+        assert_eq!(tokens[2].text, "D");
+        assert_eq!(tokens[2].span.start, 5);
+    }
+
+    #[test]
+    fn reject_malformed_tokens() {
+        assert_eq!(
+            tokenize("@1abc", 7).unwrap_err().to_string(),
+            "7:2: symbol `1abc` cannot start with a digit"
+        );
+        assert_eq!(
+            tokenize("D=D#1", 1).unwrap_err().to_string(),
+            "1:4: unexpected character `#`"
+        );
+        assert!(tokenize("@'A", 1).is_err());
+    }
+}
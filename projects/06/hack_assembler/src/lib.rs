@@ -0,0 +1,815 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use parser::{CommandType, Parser};
+
+use crate::{
+    dataflow::{check_variables, VariableReference},
+    diagnostic::{Diagnostic, Diagnostics, Location},
+    instruction::{Comp, Dest, Instruction, Jump},
+    lint::{Linter, Loaded},
+    object::Object,
+    optimizer::Removal,
+    preprocessor::{Preprocessor, MACRO_LABEL_PREFIX},
+    symbol_table::{SymbolTable, VariableAllocator, PREDEFINED_SYMBOLS},
+};
+
+pub mod cfg;
+pub mod code;
+mod dataflow;
+pub mod diagnostic;
+pub mod disassembler;
+pub mod format;
+pub mod formatter;
+pub mod instruction;
+pub mod lexer;
+pub mod linker;
+mod lint;
+pub mod listing;
+pub mod object;
+pub mod optimizer;
+pub mod parser;
+pub mod preprocessor;
+pub mod stats;
+pub mod symbol_map;
+pub mod symbol_table;
+
+/// The result of assembling a Hack program.
+#[derive(Debug)]
+pub struct Program {
+    pub words: Vec<u16>,
+    pub symbol_table: SymbolTable,
+    /// Source line of every word in `words`; words expanded from a macro or
+    /// an included file map to the line that pulled them in.
+    pub source_lines: Vec<usize>,
+    /// Constants, labels and variables in the order they were defined.
+    pub definitions: Vec<Definition>,
+    pub warnings: Vec<Diagnostic>,
+    /// Instructions the optimizer removed, if it ran.
+    pub removals: Vec<Removal>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefinitionKind {
+    Label,
+    Variable,
+    Constant,
+}
+
+/// Where a label, variable or constant got its value: the label declaration,
+/// the first A-instruction referencing a variable, or the `.equ` directive.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub address: u16,
+    pub location: Location,
+    pub kind: DefinitionKind,
+}
+
+/// Number of words in the Hack instruction memory.
+pub const ROM_SIZE: usize = 32768;
+/// First RAM address given to a variable.
+pub const VARIABLE_BASE: u16 = 16;
+/// Last RAM address before the memory-mapped screen at 16384.
+pub const DEFAULT_VARIABLE_CEILING: u16 = 16383;
+
+/// Turns assembly source into Hack machine code.
+///
+/// A label starting with `.`, such as `(.loop)`, is local to the most recent
+/// global label: it is stored as `GLOBAL.loop`, and `@.loop` only finds the
+/// one in the current scope. Labels generated by macro expansion do not open
+/// a new scope.
+pub struct Assembler {
+    file_name: String,
+    variable_ceiling: u16,
+    strict: bool,
+    optimize: bool,
+    lint: bool,
+    defines: Vec<(String, u16)>,
+}
+
+impl Assembler {
+    /// `file_name` labels diagnostics and is the base `.include` paths are
+    /// resolved against.
+    pub fn new(file_name: &str) -> Self {
+        Assembler {
+            file_name: file_name.to_string(),
+            variable_ceiling: DEFAULT_VARIABLE_CEILING,
+            strict: false,
+            optimize: false,
+            lint: false,
+            defines: vec![],
+        }
+    }
+
+    /// Defines a constant before the source is read, like `.equ` does, but
+    /// taking precedence over a `.equ` of the same name.
+    pub fn define(&mut self, name: &str, value: u16) {
+        self.defines.push((name.to_string(), value));
+    }
+
+    /// In strict mode alternate comp spellings such as `A+D` are errors.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Runs the peephole optimizer in `optimizer` before the two passes.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// Warns about legal but suspicious instruction patterns, such as a jump
+    /// to a variable's address, and about variables that may be read before
+    /// they are written or are referenced only once.
+    pub fn set_lint(&mut self, lint: bool) {
+        self.lint = lint;
+    }
+
+    /// Highest RAM address a variable may be allocated at, at most
+    /// `code::MAX_CONSTANT` so every address fits in an A-instruction.
+    pub fn set_variable_ceiling(&mut self, ceiling: u16) {
+        self.variable_ceiling = ceiling.min(code::MAX_CONSTANT);
+    }
+
+    pub fn assemble(&self, source: &str) -> Result<Program> {
+        self.translate(source, false).map(|(program, _)| program)
+    }
+
+    /// Assembles one module of a larger program for `linker::Linker`.
+    /// Symbols the module does not define become imports instead of
+    /// variables, and its global labels are exported. Warnings are kept in
+    /// `Object::warnings`.
+    pub fn assemble_object(&self, source: &str) -> Result<Object> {
+        self.translate(source, true).map(|(_, object)| object)
+    }
+
+    fn translate(&self, source: &str, relocatable: bool) -> Result<(Program, Object)> {
+        let mut object = Object {
+            name: self.file_name.clone(),
+            ..Object::default()
+        };
+        let mut preprocessor = Preprocessor::new(&self.file_name);
+        for (name, value) in self.defines.iter() {
+            preprocessor.define(name, *value);
+        }
+        let lines = preprocessor.process(source);
+        let mut pre_parser = Parser::from_lines(lines);
+        let (removals, optimizer_warning) = if self.optimize {
+            pre_parser.optimize()
+        } else {
+            (vec![], None)
+        };
+        let mut parser = pre_parser.clone();
+        let mut symbol_table = SymbolTable::new();
+
+        let mut diagnostics: Vec<Diagnostic> = preprocessor.errors().to_vec();
+        diagnostics.extend(pre_parser.errors().iter().cloned());
+        diagnostics.extend(optimizer_warning);
+
+        let mut definitions: Vec<Definition> = vec![];
+        for constant in preprocessor.constants() {
+            symbol_table.add_entry(&constant.name, constant.value);
+            definitions.push(Definition {
+                name: constant.name.clone(),
+                address: constant.value,
+                location: constant.location.clone(),
+                kind: DefinitionKind::Constant,
+            });
+        }
+
+        if pre_parser.is_empty() {
+            if !diagnostics.is_empty() {
+                return Err(Diagnostics(diagnostics).into());
+            }
+            let program = Program {
+                words: vec![],
+                symbol_table,
+                source_lines: vec![],
+                definitions,
+                warnings: vec![],
+                removals,
+            };
+            return Ok((program, object));
+        }
+
+        // first pass
+        let mut rom_address_counter: usize = 0;
+        let mut scope = String::new();
+        let mut labels: HashSet<String> = HashSet::new();
+        loop {
+            match pre_parser.command_type() {
+                CommandType::LCommand => {
+                    let mut symbol = pre_parser.symbol();
+                    let exported = !is_local(&symbol) && !symbol.starts_with(MACRO_LABEL_PREFIX);
+                    if is_local(&symbol) {
+                        if scope.is_empty() {
+                            diagnostics.push(Diagnostic::new(
+                                pre_parser.location(),
+                                pre_parser.symbol_column(),
+                                format!("local label `{}` comes before any global label", symbol),
+                            ));
+                        }
+                        symbol = format!("{}{}", scope, symbol);
+                    } else if !symbol.starts_with(MACRO_LABEL_PREFIX) {
+                        scope = symbol.clone();
+                    }
+                    if let Some(previous) = definitions
+                        .iter()
+                        .find(|definition| definition.name == symbol)
+                    {
+                        let kind = match previous.kind {
+                            DefinitionKind::Constant => "constant",
+                            _ => "label",
+                        };
+                        diagnostics.push(Diagnostic::new(
+                            pre_parser.location(),
+                            pre_parser.symbol_column(),
+                            format!(
+                                "label `{}` is already defined as a {} at {}:{}",
+                                symbol, kind, previous.location.file_name, previous.location.line
+                            ),
+                        ));
+                    } else if SymbolTable::is_predefined(&symbol) {
+                        diagnostics.push(Diagnostic::warning(
+                            pre_parser.location(),
+                            pre_parser.symbol_column(),
+                            format!("label `{}` shadows the predefined symbol", symbol),
+                        ));
+                    }
+                    symbol_table.add_entry(&symbol, rom_address_counter as u16);
+                    if exported {
+                        object
+                            .exports
+                            .push((symbol.clone(), rom_address_counter as u16));
+                    }
+                    labels.insert(symbol.clone());
+                    definitions.push(Definition {
+                        name: symbol,
+                        address: rom_address_counter as u16,
+                        location: pre_parser.location().clone(),
+                        kind: DefinitionKind::Label,
+                    });
+                }
+                _ => {
+                    rom_address_counter += 1;
+                }
+            }
+
+            if !pre_parser.has_more_commands() {
+                break;
+            }
+
+            pre_parser.advance();
+        }
+
+        // second pass
+        let mut binary_vec: Vec<u16> = vec![];
+        let mut source_lines: Vec<usize> = vec![];
+        let mut variables = VariableAllocator::new(self.variable_ceiling);
+        let mut linter = Linter::default();
+        let mut references: Vec<VariableReference> = vec![];
+        scope.clear();
+        loop {
+            match parser.command_type() {
+                CommandType::ACommand => {
+                    let symbol_numeric = parser.symbol();
+                    if self.lint {
+                        let loaded =
+                            if is_local(&symbol_numeric) || labels.contains(&symbol_numeric) {
+                                Loaded::Label(symbol_numeric.clone())
+                            } else if relocatable
+                                || code::constant(&symbol_numeric).is_some()
+                                || SymbolTable::is_predefined(&symbol_numeric)
+                                || preprocessor
+                                    .constants()
+                                    .iter()
+                                    .any(|constant| constant.name == symbol_numeric)
+                            {
+                                Loaded::Value
+                            } else {
+                                references.push(VariableReference {
+                                    address: binary_vec.len(),
+                                    name: symbol_numeric.clone(),
+                                    location: parser.location().clone(),
+                                    column: parser.symbol_column(),
+                                });
+                                Loaded::Variable(symbol_numeric.clone())
+                            };
+                        linter.a_instruction(loaded);
+                    }
+                    if is_local(&symbol_numeric) {
+                        let qualified = format!("{}{}", scope, symbol_numeric);
+                        if symbol_table.contains(&qualified) {
+                            object.relocations.push(binary_vec.len() as u16);
+                            binary_vec.push(symbol_table.get_address(&qualified));
+                        } else {
+                            let scopes: Vec<String> = definitions
+                                .iter()
+                                .filter(|definition| {
+                                    definition.kind == DefinitionKind::Label
+                                        && definition.name.ends_with(&symbol_numeric)
+                                        && !is_local(&definition.name)
+                                })
+                                .map(|definition| {
+                                    format!(
+                                        "`{}`",
+                                        &definition.name
+                                            [..definition.name.len() - symbol_numeric.len()]
+                                    )
+                                })
+                                .collect();
+                            let mut message = if scope.is_empty() {
+                                format!(
+                                    "local label `{}` is used before any global label",
+                                    symbol_numeric
+                                )
+                            } else {
+                                format!(
+                                    "local label `{}` is not defined in the scope of `{}`",
+                                    symbol_numeric, scope
+                                )
+                            };
+                            if !scopes.is_empty() {
+                                message.push_str(&format!(
+                                    "; it is defined under {}",
+                                    scopes.join(", ")
+                                ));
+                            }
+                            diagnostics.push(Diagnostic::new(
+                                parser.location(),
+                                parser.symbol_column(),
+                                message,
+                            ));
+                            binary_vec.push(0);
+                        }
+                    } else if let Some(constant) = code::constant(&symbol_numeric) {
+                        match constant {
+                            Ok(num) => binary_vec.push(num),
+                            Err(message) => {
+                                diagnostics.push(Diagnostic::new(
+                                    parser.location(),
+                                    parser.symbol_column(),
+                                    message,
+                                ));
+                                binary_vec.push(0);
+                            }
+                        }
+                    } else if symbol_table.contains(&symbol_numeric) {
+                        if labels.contains(&symbol_numeric) {
+                            object.relocations.push(binary_vec.len() as u16);
+                        }
+                        binary_vec.push(symbol_table.get_address(&symbol_numeric));
+                    } else if relocatable {
+                        object
+                            .imports
+                            .push((binary_vec.len() as u16, symbol_numeric));
+                        binary_vec.push(0);
+                    } else {
+                        if let Some((predefined, _)) =
+                            PREDEFINED_SYMBOLS.iter().find(|(predefined, _)| {
+                                predefined.eq_ignore_ascii_case(&symbol_numeric)
+                            })
+                        {
+                            diagnostics.push(Diagnostic::warning(
+                                parser.location(),
+                                parser.symbol_column(),
+                                format!(
+                                    "variable `{}` collides with the predefined symbol `{}` except for case",
+                                    symbol_numeric, predefined
+                                ),
+                            ));
+                        }
+                        let (address, first_above) = variables.allocate();
+                        if first_above {
+                            diagnostics.push(Diagnostic::new(
+                                parser.location(),
+                                parser.symbol_column(),
+                                format!(
+                                    "variable `{}` would be allocated at RAM[{}], above the ceiling RAM[{}]",
+                                    symbol_numeric, address, self.variable_ceiling
+                                ),
+                            ));
+                        }
+                        symbol_table.add_entry(&symbol_numeric, address);
+                        definitions.push(Definition {
+                            name: symbol_numeric,
+                            address,
+                            location: parser.location().clone(),
+                            kind: DefinitionKind::Variable,
+                        });
+                        binary_vec.push(address);
+                    }
+                }
+                CommandType::LCommand => {
+                    let symbol = parser.symbol();
+                    if !is_local(&symbol) && !symbol.starts_with(MACRO_LABEL_PREFIX) {
+                        scope = symbol;
+                    }
+                    linter.label();
+                }
+                CommandType::CCommand => {
+                    if self.lint {
+                        linter.c_instruction(&parser, &mut diagnostics);
+                    }
+                    // `null` only stands for an absent field, so `null=D`
+                    // and `D;null` are unknown mnemonics
+                    let dest = if parser.has_dest() {
+                        Dest::from_mnemonic(&parser.dest()).filter(|dest| *dest != Dest::Null)
+                    } else {
+                        Some(Dest::Null)
+                    };
+                    let comp = match code::canonical_comp(&parser.comp()) {
+                        Some(canonical) if self.strict && canonical != parser.comp() => {
+                            diagnostics.push(Diagnostic::new(
+                                parser.location(),
+                                parser.comp_column(),
+                                format!(
+                                    "comp `{}` is not in canonical form, write `{}`",
+                                    parser.comp(),
+                                    canonical
+                                ),
+                            ));
+                            Comp::from_mnemonic(canonical)
+                        }
+                        Some(canonical) => Comp::from_mnemonic(canonical),
+                        None => None,
+                    };
+                    // `M=0;` assembled before jumps were checked, so an
+                    // empty jump stays a no-jump with a warning
+                    let jump = if !parser.has_jump() {
+                        Some(Jump::Null)
+                    } else if parser.jump().is_empty() {
+                        diagnostics.push(Diagnostic::warning(
+                            parser.location(),
+                            parser.jump_column(),
+                            String::from("missing jump mnemonic after `;`; assembled as no jump"),
+                        ));
+                        Some(Jump::Null)
+                    } else {
+                        Jump::from_mnemonic(&parser.jump()).filter(|jump| *jump != Jump::Null)
+                    };
+                    if parser.dest().is_empty() {
+                        diagnostics.push(Diagnostic::new(
+                            parser.location(),
+                            parser.dest_column(),
+                            String::from("missing dest mnemonic before `=`"),
+                        ));
+                    } else if dest.is_none() {
+                        diagnostics.push(Diagnostic::new(
+                            parser.location(),
+                            parser.dest_column(),
+                            format!("unknown dest `{}`", parser.dest()),
+                        ));
+                    }
+                    if comp.is_none() {
+                        diagnostics.push(Diagnostic::new(
+                            parser.location(),
+                            parser.comp_column(),
+                            format!("unknown comp `{}`", parser.comp()),
+                        ));
+                    }
+                    if jump.is_none() {
+                        diagnostics.push(Diagnostic::new(
+                            parser.location(),
+                            parser.jump_column(),
+                            format!("unknown jump `{}`", parser.jump()),
+                        ));
+                    }
+                    let instruction = Instruction::C {
+                        dest: dest.unwrap_or(Dest::Null),
+                        comp: comp.unwrap_or(Comp::Zero),
+                        jump: jump.unwrap_or(Jump::Null),
+                    };
+                    binary_vec.push(instruction.encode());
+                }
+            }
+
+            if source_lines.len() < binary_vec.len() {
+                source_lines.push(parser.location().root_line());
+            }
+
+            if !parser.has_more_commands() {
+                break;
+            }
+
+            parser.advance();
+        }
+
+        if binary_vec.len() > ROM_SIZE {
+            diagnostics.push(Diagnostic::new(
+                &Location::new(&self.file_name, source_lines[ROM_SIZE]),
+                1,
+                format!(
+                    "program needs {} words but the ROM holds {}; this is the first instruction that does not fit",
+                    binary_vec.len(),
+                    ROM_SIZE
+                ),
+            ));
+        }
+
+        if diagnostics.iter().any(|diagnostic| diagnostic.is_error()) {
+            if binary_vec.len() > ROM_SIZE || variables.overflowed() {
+                let available =
+                    (self.variable_ceiling as u32 + 1).saturating_sub(VARIABLE_BASE as u32);
+                diagnostics.push(Diagnostic::note(format!(
+                    "memory used: ROM {} of {} words, variable RAM {} of {} words (RAM[{}..={}])",
+                    binary_vec.len(),
+                    ROM_SIZE,
+                    variables.count(),
+                    available,
+                    VARIABLE_BASE,
+                    self.variable_ceiling
+                )));
+            }
+            return Err(Diagnostics(diagnostics).into());
+        }
+
+        object.words = binary_vec.clone();
+        let mut program = Program {
+            words: binary_vec,
+            symbol_table,
+            source_lines,
+            definitions,
+            warnings: diagnostics,
+            removals,
+        };
+        if self.lint {
+            let warnings = check_variables(&program, &references);
+            program.warnings.extend(warnings);
+        }
+        object.warnings = program.warnings.clone();
+        Ok((program, object))
+    }
+}
+
+/// Assembles `source` without touching the filesystem.
+pub fn assemble(source: &str) -> Result<Program> {
+    Assembler::new("<input>").assemble(source)
+}
+
+fn is_local(symbol: &str) -> bool {
+    symbol.starts_with('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble, Assembler, ROM_SIZE};
+    use crate::diagnostic::Diagnostics;
+
+    fn hack_words(hack: &str) -> Vec<u16> {
+        hack.lines()
+            .map(|line| u16::from_str_radix(line, 2).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn assemble_max() {
+        let program = assemble(include_str!("../../max/Max.asm")).unwrap();
+        assert_eq!(
+            program.words,
+            hack_words(include_str!("../../../05/Max.hack"))
+        );
+        assert_eq!(
+            program
+                .symbol_table
+                .get_address(&String::from("OUTPUT_FIRST")),
+            10
+        );
+    }
+
+    #[test]
+    fn assemble_rect() {
+        let program = assemble(include_str!("../../rect/Rect.asm")).unwrap();
+        assert_eq!(
+            program.words,
+            hack_words(include_str!("../../../05/Rect.hack"))
+        );
+    }
+
+    #[test]
+    fn handle_empty_dest_and_jump() {
+        let program = assemble("@COLOR\nM = 0;\n").unwrap();
+        assert_eq!(program.words[1], 0b1110101010001000);
+        assert_eq!(
+            program.warnings[0].to_string(),
+            "<input>:2:7: warning: missing jump mnemonic after `;`; assembled as no jump"
+        );
+
+        let error = assemble("=M\n").unwrap_err();
+        let diagnostics = error.downcast::<Diagnostics>().unwrap();
+        assert_eq!(
+            diagnostics.0[0].to_string(),
+            "<input>:1:2: error: missing dest mnemonic before `=`"
+        );
+    }
+
+    #[test]
+    fn reject_null_written_in_the_source() {
+        let error = assemble("null=D\nD;null\n").unwrap_err();
+        let diagnostics = error.downcast::<Diagnostics>().unwrap();
+        let messages: Vec<String> = diagnostics.0.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            &messages[..2],
+            [
+                "<input>:1:1: error: unknown dest `null`",
+                "<input>:2:3: error: unknown jump `null`",
+            ]
+        );
+    }
+
+    #[test]
+    fn assemble_empty() {
+        assert!(assemble("// nothing here\n\n").unwrap().words.is_empty());
+    }
+
+    #[test]
+    fn numeric_literals() {
+        let program = assemble("@0x4000\n@0b1010\n@'A'\n@32767\n").unwrap();
+        assert_eq!(program.words, vec![16384, 10, 65, 32767]);
+    }
+
+    #[test]
+    fn reject_constants_wider_than_15_bits() {
+        let error = assemble("@1\n@32768\n@0xFFFF\n@0x\n").unwrap_err();
+        let diagnostics = error.downcast::<Diagnostics>().unwrap();
+        assert_eq!(
+            diagnostics.0[0].to_string(),
+            "<input>:2:2: error: constant `32768` does not fit in 15 bits (maximum is 32767)"
+        );
+        assert_eq!(
+            diagnostics.0[1].to_string(),
+            "<input>:3:2: error: constant `0xFFFF` does not fit in 15 bits (maximum is 32767)"
+        );
+        assert_eq!(
+            diagnostics.0[2].to_string(),
+            "<input>:4:2: error: invalid numeric constant `0x`"
+        );
+    }
+
+    #[test]
+    fn reject_duplicate_labels() {
+        let error = assemble("(LOOP)\n@LOOP\n0;JMP\n(LOOP)\n").unwrap_err();
+        let diagnostics = error.downcast::<Diagnostics>().unwrap();
+        assert_eq!(
+            diagnostics.0[0].to_string(),
+            "<input>:4:2: error: label `LOOP` is already defined as a label at <input>:1"
+        );
+    }
+
+    #[test]
+    fn warn_about_predefined_symbol_collisions() {
+        let program = assemble("(SCREEN)\n@kbd\nD=M\n").unwrap();
+        let warnings: Vec<String> = program.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "<input>:1:2: warning: label `SCREEN` shadows the predefined symbol",
+                "<input>:2:2: warning: variable `kbd` collides with the predefined symbol `KBD` except for case",
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_variables_above_the_ceiling() {
+        let mut assembler = Assembler::new("<input>");
+        assembler.set_variable_ceiling(17);
+        assert!(assembler.assemble("@a\n@b\n@a\n").is_ok());
+
+        let error = assembler.assemble("@a\n@b\n@c\n@d\n").unwrap_err();
+        let diagnostics = error.downcast::<Diagnostics>().unwrap();
+        assert_eq!(diagnostics.0.len(), 2);
+        assert_eq!(
+            diagnostics.0[0].to_string(),
+            "<input>:3:2: error: variable `c` would be allocated at RAM[18], above the ceiling RAM[17]"
+        );
+        assert_eq!(
+            diagnostics.0[1].to_string(),
+            "note: memory used: ROM 4 of 32768 words, variable RAM 4 of 2 words (RAM[16..=17])"
+        );
+
+        assembler.set_variable_ceiling(10);
+        let error = assembler.assemble("@a\n@b\nM=1\n").unwrap_err();
+        let diagnostics = error.downcast::<Diagnostics>().unwrap();
+        assert_eq!(
+            diagnostics.0[0].to_string(),
+            "<input>:1:2: error: variable `a` would be allocated at RAM[16], above the ceiling RAM[10]"
+        );
+        assert_eq!(diagnostics.0.len(), 2);
+    }
+
+    #[test]
+    fn keep_variables_within_a_instruction_range() {
+        let mut assembler = Assembler::new("<input>");
+        assembler.set_variable_ceiling(u16::MAX);
+        let source: String = (16..=32768).map(|n| format!("@v{}\n", n)).collect();
+        let error = assembler.assemble(&source).unwrap_err();
+        let diagnostics = error.downcast::<Diagnostics>().unwrap();
+        assert_eq!(
+            diagnostics.0[0].to_string(),
+            "<input>:32753:2: error: variable `v32768` would be allocated at RAM[32768], above the ceiling RAM[32767]"
+        );
+    }
+
+    #[test]
+    fn reject_programs_larger_than_rom() {
+        let source = "D=D+1\n".repeat(ROM_SIZE + 2);
+        let error = assemble(&source).unwrap_err();
+        let diagnostics = error.downcast::<Diagnostics>().unwrap();
+        assert_eq!(
+            diagnostics.0[0].to_string(),
+            "<input>:32769:1: error: program needs 32770 words but the ROM holds 32768; this is the first instruction that does not fit"
+        );
+    }
+
+    #[test]
+    fn accept_alternate_comp_spellings() {
+        let canonical = assemble("D=D+A\nM=D|M\nD=D+1\nAM=D&M\n").unwrap();
+        let alternate = assemble("D=A+D\nM=M|D\nD=1+D\nAM=M&D\n").unwrap();
+        assert_eq!(canonical.words, alternate.words);
+    }
+
+    #[test]
+    fn strict_mode_suggests_canonical_comp() {
+        let mut assembler = Assembler::new("<input>");
+        assembler.set_strict(true);
+        assert!(assembler.assemble("D=D+A\n").is_ok());
+
+        let error = assembler.assemble("D=A+D\n").unwrap_err();
+        let diagnostics = error.downcast::<Diagnostics>().unwrap();
+        assert_eq!(
+            diagnostics.0[0].to_string(),
+            "<input>:1:3: error: comp `A+D` is not in canonical form, write `D+A`"
+        );
+    }
+
+    #[test]
+    fn errors_in_macros_name_the_call_site() {
+        let error = assemble(".macro BAD\nD=D+Q\n.endm\n@R0\nBAD\n").unwrap_err();
+        let diagnostics = error.downcast::<Diagnostics>().unwrap();
+        assert_eq!(
+            diagnostics.0[0].to_string(),
+            "<input>:2:3: error: unknown comp `D+Q`\n    in expansion of macro `BAD` at <input>:5"
+        );
+    }
+
+    #[test]
+    fn scope_local_labels() {
+        let source = "\
+(FIRST)
+(.loop)
+    @.loop
+    0;JMP
+(SECOND)
+    @.loop
+    0;JMP
+(.loop)
+    @FIRST.loop
+";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            program.words,
+            vec![0, 0b1110101010000111, 4, 0b1110101010000111, 0]
+        );
+
+        let error = assemble("@.skip\n(MAIN)\n(.loop)\n(OTHER)\n@.loop\n").unwrap_err();
+        let errors: Vec<String> = error
+            .downcast_ref::<Diagnostics>()
+            .unwrap()
+            .0
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "<input>:1:2: error: local label `.skip` is used before any global label",
+                "<input>:5:2: error: local label `.loop` is not defined in the scope of `OTHER`; it is defined under `MAIN`",
+            ]
+        );
+    }
+
+    #[test]
+    fn constants_take_no_memory() {
+        let mut assembler = Assembler::new("<input>");
+        assembler.define("FILL", 0x7FFF);
+        let program = assembler
+            .assemble(".equ SIZE 8192\n@SIZE\nD=A\n@FILL\n@var\n")
+            .unwrap();
+        assert_eq!(program.words, vec![8192, 0b1110110000010000, 0x7FFF, 16]);
+    }
+
+    #[test]
+    fn report_every_unknown_mnemonic() {
+        let error = assemble("@0\nD=D+Q\n  // comment\nD;JXX\n").unwrap_err();
+        let diagnostics = error.downcast::<Diagnostics>().unwrap();
+        assert_eq!(diagnostics.0.len(), 2);
+        assert_eq!(
+            diagnostics.0[0].to_string(),
+            "<input>:2:3: error: unknown comp `D+Q`"
+        );
+        assert_eq!(
+            diagnostics.0[1].to_string(),
+            "<input>:4:3: error: unknown jump `JXX`"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    code,
+    object::Object,
+    symbol_table::{SymbolTable, VariableAllocator},
+    DEFAULT_VARIABLE_CEILING, ROM_SIZE,
+};
+
+/// A complete program made from linked objects.
+#[derive(Debug)]
+pub struct Linked {
+    pub words: Vec<u16>,
+    /// Predefined symbols, every export at its final ROM address, and the
+    /// variables allocated while resolving imports.
+    pub symbol_table: SymbolTable,
+}
+
+/// Combines objects into one program.
+///
+/// Modules are placed in ROM in the order given, so the first one holds the
+/// entry point. An import resolves to the label of that name exported by any
+/// module; otherwise it is a variable, and modules using the same variable
+/// name share its RAM address, as they would in a single source file.
+pub struct Linker {
+    variable_ceiling: u16,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Linker {
+            variable_ceiling: DEFAULT_VARIABLE_CEILING,
+        }
+    }
+
+    /// Highest RAM address a variable may be allocated at, at most
+    /// `code::MAX_CONSTANT` so every address fits in an A-instruction.
+    pub fn set_variable_ceiling(&mut self, ceiling: u16) {
+        self.variable_ceiling = ceiling.min(code::MAX_CONSTANT);
+    }
+
+    pub fn link(&self, objects: &[Object]) -> Result<Linked> {
+        let mut symbol_table = SymbolTable::new();
+        let mut exported_by: HashMap<&str, &str> = HashMap::new();
+        let mut errors: Vec<String> = vec![];
+
+        // first pass: place every module and its exports
+        let mut bases = vec![];
+        let mut rom_address_counter: usize = 0;
+        for object in objects.iter() {
+            for (name, address) in object.exports.iter() {
+                if let Some(previous) = exported_by.insert(name, &object.name) {
+                    errors.push(format!(
+                        "label `{}` is exported by both {} and {}",
+                        name, previous, object.name
+                    ));
+                }
+                symbol_table.add_entry(name, (rom_address_counter + *address as usize) as u16);
+            }
+            bases.push(rom_address_counter);
+            rom_address_counter += object.words.len();
+        }
+        if rom_address_counter > ROM_SIZE {
+            errors.push(format!(
+                "program needs {} words but the ROM holds {}",
+                rom_address_counter, ROM_SIZE
+            ));
+        }
+
+        // second pass: relocate, resolve imports and allocate variables
+        let mut words: Vec<u16> = Vec::with_capacity(rom_address_counter);
+        let mut variables = VariableAllocator::new(self.variable_ceiling);
+        for (object, base) in objects.iter().zip(bases) {
+            let mut module = object.words.clone();
+            for index in object.relocations.iter() {
+                module[*index as usize] = module[*index as usize].wrapping_add(base as u16);
+            }
+            for (index, name) in object.imports.iter() {
+                if !symbol_table.contains(name) {
+                    let (address, first_above) = variables.allocate();
+                    if first_above {
+                        errors.push(format!(
+                            "variable `{}` used in {} would be allocated at RAM[{}], above the ceiling RAM[{}]",
+                            name, object.name, address, self.variable_ceiling
+                        ));
+                    }
+                    symbol_table.add_entry(name, address);
+                }
+                module[*index as usize] = symbol_table.get_address(name);
+            }
+            words.extend(module);
+        }
+
+        if !errors.is_empty() {
+            return Err(anyhow!(errors.join("\n")));
+        }
+        Ok(Linked {
+            words,
+            symbol_table,
+        })
+    }
+}
+
+impl Default for Linker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Linker;
+    use crate::{assemble, Assembler};
+
+    #[test]
+    fn link_like_a_single_file() {
+        let main = "(MAIN)\n@counter\nM=1\n@PRINT\n0;JMP\n";
+        let print = "(PRINT)\n@counter\nD=M\n(.halt)\n@.halt\n0;JMP\n@last\n";
+        let objects = vec![
+            Assembler::new("main.asm").assemble_object(main).unwrap(),
+            Assembler::new("print.asm").assemble_object(print).unwrap(),
+        ];
+
+        let linked = Linker::new().link(&objects).unwrap();
+        let whole = assemble(&format!("{}{}", main, print)).unwrap();
+        assert_eq!(linked.words, whole.words);
+        assert_eq!(linked.symbol_table.get_address(&"PRINT".to_string()), 4);
+        assert_eq!(linked.symbol_table.get_address(&"last".to_string()), 17);
+    }
+
+    #[test]
+    fn report_link_errors() {
+        let objects = vec![
+            Assembler::new("a.asm")
+                .assemble_object("(X)\n@a\n")
+                .unwrap(),
+            Assembler::new("b.asm")
+                .assemble_object("(X)\n@b\n")
+                .unwrap(),
+        ];
+        let mut linker = Linker::new();
+        linker.set_variable_ceiling(16);
+        assert_eq!(
+            linker.link(&objects).unwrap_err().to_string(),
+            "label `X` is exported by both a.asm and b.asm\n\
+             variable `b` used in b.asm would be allocated at RAM[17], above the ceiling RAM[16]"
+        );
+
+        linker.set_variable_ceiling(10);
+        assert_eq!(
+            linker.link(&objects[..1]).unwrap_err().to_string(),
+            "variable `a` used in a.asm would be allocated at RAM[16], above the ceiling RAM[10]"
+        );
+    }
+}
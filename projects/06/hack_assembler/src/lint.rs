@@ -0,0 +1,137 @@
+use crate::{diagnostic::Diagnostic, parser::Parser};
+
+/// What the most recent A-instruction loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Loaded {
+    /// The ROM address of a label.
+    Label(String),
+    /// The RAM address of a variable.
+    Variable(String),
+    /// A number, constant, predefined symbol or import.
+    Value,
+}
+
+/// Flags legal instruction patterns that are almost always bugs. It follows
+/// the second pass one command at a time and only looks at straight-line
+/// code, forgetting what A holds at every label.
+#[derive(Debug, Default)]
+pub(crate) struct Linter {
+    /// What A holds, if it was set by an A-instruction.
+    loaded: Option<Loaded>,
+}
+
+impl Linter {
+    pub(crate) fn label(&mut self) {
+        self.loaded = None;
+    }
+
+    pub(crate) fn a_instruction(&mut self, loaded: Loaded) {
+        self.loaded = Some(loaded);
+    }
+
+    pub(crate) fn c_instruction(&mut self, parser: &Parser, diagnostics: &mut Vec<Diagnostic>) {
+        let dest = parser.dest();
+        let jump = parser.jump();
+        let jumps = parser.has_jump() && !jump.is_empty();
+
+        if jumps && dest.contains('A') {
+            diagnostics.push(Diagnostic::warning(
+                parser.location(),
+                parser.dest_column(),
+                format!(
+                    "`{}={};{}` writes A and jumps; the jump goes to the old A, not the value written",
+                    dest,
+                    parser.comp(),
+                    jump
+                ),
+            ));
+        }
+
+        match &self.loaded {
+            Some(Loaded::Label(label)) if parser.comp().contains('M') => {
+                diagnostics.push(Diagnostic::warning(
+                    parser.location(),
+                    parser.comp_column(),
+                    format!(
+                        "reading M after `@{}` uses the ROM address of a label as a RAM address",
+                        label
+                    ),
+                ));
+            }
+            Some(Loaded::Variable(variable)) if jumps => {
+                diagnostics.push(Diagnostic::warning(
+                    parser.location(),
+                    parser.jump_column(),
+                    format!(
+                        "jumping to `{}` goes to its RAM address as if it were ROM; write `@{} A=M` to jump to the address it holds",
+                        variable, variable
+                    ),
+                ));
+            }
+            _ => {}
+        }
+
+        if dest.contains('A') {
+            self.loaded = None;
+        }
+    }
+}
+
+/// The warnings from assembling `source` with the lint pass on.
+#[cfg(test)]
+pub(crate) fn lint(source: &str) -> Vec<String> {
+    let mut assembler = crate::Assembler::new("<input>");
+    assembler.set_lint(true);
+    assembler
+        .assemble(source)
+        .unwrap()
+        .warnings
+        .iter()
+        .map(|warning| warning.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lint;
+
+    #[test]
+    fn flag_hazards() {
+        let source = "\
+(LOOP)
+    @SP
+    AM=M-1;JMP
+    @LOOP
+    D=M
+    @target
+    0;JMP
+";
+        assert_eq!(
+            lint(source),
+            vec![
+                "<input>:3:5: warning: `AM=M-1;JMP` writes A and jumps; the jump goes to the old A, not the value written",
+                "<input>:5:7: warning: reading M after `@LOOP` uses the ROM address of a label as a RAM address",
+                "<input>:7:7: warning: jumping to `target` goes to its RAM address as if it were ROM; write `@target A=M` to jump to the address it holds",
+                "<input>:6:6: warning: variable `target` is referenced only once; is it misspelled?",
+            ]
+        );
+    }
+
+    #[test]
+    fn accept_common_patterns() {
+        let source = "\
+(LOOP)
+    @LOOP
+    D=A
+    @ret
+    M=D
+    @ret
+    A=M
+    0;JMP
+    @LOOP
+    D;JGT
+";
+        assert!(lint(source).is_empty());
+        assert!(lint("@LOOP\n(LOOP)\nD=M\n").is_empty());
+    }
+}
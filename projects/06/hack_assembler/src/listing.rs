@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use crate::{DefinitionKind, Program};
+
+/// Renders `source` next to the machine code assembled from it.
+///
+/// Each line shows the source line number, the ROM address, the encoding in
+/// binary and hex, and the original text. Labels show the address they resolve
+/// to and the first reference to a variable shows the RAM slot it was given.
+pub fn listing(source: &str, program: &Program) -> String {
+    let mut words_by_line: HashMap<usize, Vec<(usize, u16)>> = HashMap::new();
+    for (address, (line, word)) in program
+        .source_lines
+        .iter()
+        .zip(program.words.iter())
+        .enumerate()
+    {
+        words_by_line
+            .entry(*line)
+            .or_default()
+            .push((address, *word));
+    }
+
+    let mut text = String::from("LINE  ADDR  BINARY            HEX     SOURCE\n");
+    for (index, source_line) in source.lines().enumerate() {
+        let line = index + 1;
+        let definition = program
+            .definitions
+            .iter()
+            .find(|definition| definition.location.root_line() == line);
+        let words = words_by_line.get(&line).map_or(&[][..], |words| &words[..]);
+
+        let mut entry = match (words.first(), definition) {
+            (Some((address, word)), _) => format!(
+                "{:>4}  {:04}  {:016b}  0x{:04X}  {}",
+                line, address, word, word, source_line
+            ),
+            (None, Some(definition)) => format!(
+                "{:>4}  {:04}  {:16}  {:6}  {}",
+                line, definition.address, "", "", source_line
+            ),
+            (None, None) => format!(
+                "{:>4}  {:4}  {:16}  {:6}  {}",
+                line, "", "", "", source_line
+            ),
+        };
+
+        if let Some(definition) = definition {
+            match definition.kind {
+                DefinitionKind::Label => entry.push_str(&format!(
+                    "    ; label {} = ROM[{}]",
+                    definition.name, definition.address
+                )),
+                DefinitionKind::Variable => entry.push_str(&format!(
+                    "    ; variable {} = RAM[{}]",
+                    definition.name, definition.address
+                )),
+                DefinitionKind::Constant => entry.push_str(&format!(
+                    "    ; constant {} = {}",
+                    definition.name, definition.address
+                )),
+            }
+        }
+
+        text.push_str(entry.trim_end());
+        text.push('\n');
+
+        // a macro call or include expands to several words on one line
+        for (address, word) in words.iter().skip(1) {
+            text.push_str(&format!(
+                "{:>4}  {:04}  {:016b}  0x{:04X}\n",
+                "", address, word, word
+            ));
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::listing;
+    use crate::assemble;
+
+    #[test]
+    fn listing_shows_labels_and_variables() {
+        let source = "// count down\n(LOOP)\n@i\nM=M-1\n@LOOP\n0;JMP\n";
+        let program = assemble(source).unwrap();
+        let lines: Vec<String> = listing(source, &program)
+            .lines()
+            .map(|line| line.to_string())
+            .collect();
+
+        assert_eq!(
+            lines[1],
+            "   1                                  // count down"
+        );
+        assert_eq!(
+            lines[2],
+            "   2  0000                            (LOOP)    ; label LOOP = ROM[0]"
+        );
+        assert_eq!(
+            lines[3],
+            "   3  0000  0000000000010000  0x0010  @i    ; variable i = RAM[16]"
+        );
+        assert_eq!(lines[6], "   6  0003  1110101010000111  0xEA87  0;JMP");
+    }
+}
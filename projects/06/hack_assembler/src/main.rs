@@ -1,94 +1,345 @@
-use std::{env, fs::File, io::Write};
+use std::{env, fs, fs::File, io::Write, path::Path};
 
-use anyhow::{Context, Result, anyhow};
-use parser::{CommandType, Parser};
+use anyhow::{anyhow, Context, Result};
+use hack_assembler::{
+    cfg::ControlFlowGraph,
+    code, disassembler,
+    format::{self, Format},
+    formatter,
+    linker::Linker,
+    listing, object, optimizer,
+    stats::Stats,
+    symbol_map, Assembler,
+};
 
-use crate::symbol_table::SymbolTable;
-mod code;
-mod parser;
-mod symbol_table;
+const USAGE: &str = "usage: hack_assembler [options] <file.asm | file.hack>
+       hack_assembler --link=out.hack <module.asm | module.hobj>...
+       hack_assembler --fmt [--check] <file.asm>...
+
+options:
+    --listing                 write a .lst listing next to the .hack file
+    --symbols[=text|json]     write the symbol map to .sym or .sym.json
+    --variable-ceiling=N      highest RAM address a variable may use (default 16383)
+    --strict                  reject alternate comp spellings such as A+D
+    -D NAME=value             define a constant, overriding `.equ NAME value`
+    --cfg                     write the control-flow graph as Graphviz DOT to .dot
+    --stats[=text|json]       write instruction counts and routine sizes to .stats or .stats.json
+    --lint                    warn about suspicious instruction patterns
+    --optimize                remove redundant instructions and report the words saved
+    --object                  write a relocatable .hobj object instead of .hack
+    --link=FILE               link the given modules into FILE
+    --fmt                     rewrite the files in the canonical layout
+    --check                   with --fmt, only fail if a file is not formatted
+    --format=NAME             output format: hack (default), bin, hex, logisim, rust, c
+
+Files ending in .hack, .bin, .hex, .rom, .rs or .c are disassembled.";
+
+enum ReportFormat {
+    Text,
+    Json,
+}
+
+struct Options {
+    file_path: String,
+    /// Modules after the first one when linking.
+    modules: Vec<String>,
+    object: bool,
+    optimize: bool,
+    lint: bool,
+    link: Option<String>,
+    fmt: bool,
+    check: bool,
+    format: Format,
+    listing: bool,
+    cfg: bool,
+    symbols: Option<ReportFormat>,
+    stats: Option<ReportFormat>,
+    variable_ceiling: Option<u16>,
+    strict: bool,
+    defines: Vec<(String, u16)>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options> {
+    let mut paths = vec![];
+    let mut object = false;
+    let mut optimize = false;
+    let mut lint = false;
+    let mut link = None;
+    let mut format = Format::Hack;
+    let mut fmt = false;
+    let mut check = false;
+    let mut listing = false;
+    let mut cfg = false;
+    let mut symbols = None;
+    let mut stats = None;
+    let mut variable_ceiling = None;
+    let mut strict = false;
+    let mut defines = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-D" => {
+                let define = args.next().context("-D needs NAME=value")?;
+                defines.push(parse_define(define)?);
+            }
+            flag if flag.starts_with("-D") => defines.push(parse_define(&flag[2..])?),
+            "--listing" => listing = true,
+            "--cfg" => cfg = true,
+            "--strict" => strict = true,
+            "--object" => object = true,
+            "--optimize" => optimize = true,
+            "--lint" => lint = true,
+            "--fmt" => fmt = true,
+            "--check" => check = true,
+            flag if flag.starts_with("--link=") => link = Some(flag["--link=".len()..].to_string()),
+            flag if flag.starts_with("--format=") => {
+                let name = &flag["--format=".len()..];
+                format = Format::from_name(name)
+                    .with_context(|| format!("Unknown output format: {}", name))?;
+            }
+            "--symbols" | "--symbols=text" => symbols = Some(ReportFormat::Text),
+            "--symbols=json" => symbols = Some(ReportFormat::Json),
+            "--stats" | "--stats=text" => stats = Some(ReportFormat::Text),
+            "--stats=json" => stats = Some(ReportFormat::Json),
+            flag if flag.starts_with("--variable-ceiling=") => {
+                let value = &flag["--variable-ceiling=".len()..];
+                let ceiling = value
+                    .parse::<u16>()
+                    .with_context(|| format!("Invalid variable ceiling: {}", value))?;
+                if ceiling > code::MAX_CONSTANT {
+                    return Err(anyhow!(
+                        "Invalid variable ceiling: {} is above {}, the largest address an A-instruction can load",
+                        ceiling,
+                        code::MAX_CONSTANT
+                    ));
+                }
+                variable_ceiling = Some(ceiling);
+            }
+            flag if flag.starts_with("--") => return Err(anyhow!("Unknown option: {}", flag)),
+            path => paths.push(path.to_string()),
+        }
+    }
+    if paths.is_empty() || (paths.len() > 1 && link.is_none() && !fmt) {
+        return Err(anyhow!(USAGE));
+    }
+
+    Ok(Options {
+        file_path: paths.remove(0),
+        modules: paths,
+        object,
+        optimize,
+        lint,
+        link,
+        fmt,
+        check,
+        format,
+        listing,
+        cfg,
+        symbols,
+        stats,
+        variable_ceiling,
+        strict,
+        defines,
+    })
+}
+
+fn parse_define(define: &str) -> Result<(String, u16)> {
+    let (name, value) = define
+        .split_once('=')
+        .with_context(|| format!("Invalid define, expected NAME=value: {}", define))?;
+    let value = match code::constant(value) {
+        Some(Ok(value)) => value,
+        Some(Err(message)) => return Err(anyhow!("Invalid define {}: {}", name, message)),
+        None => {
+            return Err(anyhow!(
+                "Invalid define {}: `{}` is not a number",
+                name,
+                value
+            ))
+        }
+    };
+    Ok((name.to_string(), value))
+}
 
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
-    let file_path = &args[1];
+    let options = parse_args(&args[1..])?;
+    let file_path = &options.file_path;
+    if options.fmt {
+        return format_files(&options);
+    }
+    if let Some(output_file_name) = options.link.as_ref() {
+        return link_files(&options, output_file_name);
+    }
     if !file_path.ends_with(".asm") {
+        let extension = Path::new(file_path)
+            .extension()
+            .and_then(|extension| extension.to_str());
+        if let Some(format) = extension.and_then(Format::from_extension) {
+            return disassemble_file(file_path, format);
+        }
         return Err(anyhow!("Invalid file extension: {}", file_path));
     }
-    let file = File::open(file_path).with_context(|| format!("not find {}", file_path))?;
+    let source =
+        fs::read_to_string(file_path).with_context(|| format!("not find {}", file_path))?;
 
-    let mut pre_parser = Parser::new(file)?;
-    let mut parser = pre_parser.clone();
-    let mut symbol_table = SymbolTable::new();
+    let assembler = assembler(file_path, &options);
+    if options.object {
+        let object = assembler.assemble_object(&source)?;
+        for warning in object.warnings.iter() {
+            eprintln!("{}", warning);
+        }
+        let object_file_name = file_path.replace(".asm", ".hobj");
+        fs::write(&object_file_name, object::write_object(&object))
+            .with_context(|| format!("failed to write {}", object_file_name))?;
+        return Ok(());
+    }
+    let program = assembler.assemble(&source)?;
+    for warning in program.warnings.iter() {
+        eprintln!("{}", warning);
+    }
+    if options.optimize {
+        eprintln!("optimizer: {}", optimizer::report(&program.removals));
+    }
 
-    // first pass
-    let mut rom_address_counter = 0;
-    loop {
-        match pre_parser.command_type() {
-            CommandType::LCommand => {
-                let symbol = pre_parser.symbol();
-                symbol_table.add_entry(&symbol, rom_address_counter);
-            }
-            _ => {
-                rom_address_counter += 1;
-            }
+    if options.listing {
+        let listing_file_name = file_path.replace(".asm", ".lst");
+        fs::write(&listing_file_name, listing::listing(&source, &program))
+            .with_context(|| format!("failed to write {}", listing_file_name))?;
+    }
+
+    if options.cfg {
+        let dot_file_name = file_path.replace(".asm", ".dot");
+        fs::write(&dot_file_name, ControlFlowGraph::new(&program).dot())
+            .with_context(|| format!("failed to write {}", dot_file_name))?;
+    }
+
+    match options.symbols {
+        Some(ReportFormat::Text) => {
+            let symbols_file_name = file_path.replace(".asm", ".sym");
+            fs::write(&symbols_file_name, symbol_map::symbols_text(&program))
+                .with_context(|| format!("failed to write {}", symbols_file_name))?;
         }
+        Some(ReportFormat::Json) => {
+            let symbols_file_name = file_path.replace(".asm", ".sym.json");
+            fs::write(&symbols_file_name, symbol_map::symbols_json(&program))
+                .with_context(|| format!("failed to write {}", symbols_file_name))?;
+        }
+        None => {}
+    }
 
-        if !pre_parser.has_more_commands() {
-            break;
+    match options.stats {
+        Some(ReportFormat::Text) => {
+            let stats_file_name = file_path.replace(".asm", ".stats");
+            fs::write(&stats_file_name, Stats::new(&program).text())
+                .with_context(|| format!("failed to write {}", stats_file_name))?;
         }
+        Some(ReportFormat::Json) => {
+            let stats_file_name = file_path.replace(".asm", ".stats.json");
+            fs::write(&stats_file_name, Stats::new(&program).json())
+                .with_context(|| format!("failed to write {}", stats_file_name))?;
+        }
+        None => {}
+    }
 
-        pre_parser.advance();
-    }
-
-    // second pass
-    let mut binary_vec: Vec<u16> = vec![];
-    let mut ram_address_counter = 16;
-    loop {
-        match parser.command_type() {
-            CommandType::ACommand => {
-                let symbol_numeric = parser.symbol();
-                if let Ok(num) = symbol_numeric.parse::<u16>() {
-                    binary_vec.push(num);
-                } else if symbol_table.contains(&symbol_numeric) {
-                    binary_vec.push(symbol_table.get_address(&symbol_numeric));
-                } else {
-                    symbol_table.add_entry(&symbol_numeric, ram_address_counter);
-                    binary_vec.push(ram_address_counter);
-                    ram_address_counter += 1;
-                }
-            }
-            CommandType::LCommand => { /*  nothing to do */ }
-            CommandType::CCommand => {
-                let dest = code::dest(&parser.dest());
-                let comp = code::comp(&parser.comp());
-                let jump = code::jump(&parser.jump());
-                binary_vec.push(sum(dest, comp, jump));
+    write_file(file_path, &program.words, options.format)?;
+    Ok(())
+}
+
+fn assembler(file_path: &str, options: &Options) -> Assembler {
+    let mut assembler = Assembler::new(file_path);
+    if let Some(ceiling) = options.variable_ceiling {
+        assembler.set_variable_ceiling(ceiling);
+    }
+    assembler.set_strict(options.strict);
+    assembler.set_optimize(options.optimize);
+    assembler.set_lint(options.lint);
+    for (name, value) in options.defines.iter() {
+        assembler.define(name, *value);
+    }
+    assembler
+}
+
+// modules may be sources, assembled on the way, or objects
+fn link_files(options: &Options, output_file_name: &str) -> Result<()> {
+    let mut objects = vec![];
+    for file_path in std::iter::once(&options.file_path).chain(options.modules.iter()) {
+        let text =
+            fs::read_to_string(file_path).with_context(|| format!("not find {}", file_path))?;
+        let object = if file_path.ends_with(".hobj") {
+            object::parse_object(file_path, &text)?
+        } else if file_path.ends_with(".asm") {
+            let object = assembler(file_path, options).assemble_object(&text)?;
+            for warning in object.warnings.iter() {
+                eprintln!("{}", warning);
             }
-        }
+            object
+        } else {
+            return Err(anyhow!("Invalid file extension: {}", file_path));
+        };
+        objects.push(object);
+    }
 
-        if !parser.has_more_commands() {
-            break;
-        }
+    let mut linker = Linker::new();
+    if let Some(ceiling) = options.variable_ceiling {
+        linker.set_variable_ceiling(ceiling);
+    }
+    let linked = linker.link(&objects)?;
+    fs::write(
+        output_file_name,
+        format::encode(&linked.words, options.format),
+    )
+    .with_context(|| format!("failed to write {}", output_file_name))
+}
 
-        parser.advance();
+fn format_files(options: &Options) -> Result<()> {
+    let mut unformatted = vec![];
+    for file_path in std::iter::once(&options.file_path).chain(options.modules.iter()) {
+        let source =
+            fs::read_to_string(file_path).with_context(|| format!("not find {}", file_path))?;
+        let formatted = formatter::format_source(&source);
+        if formatted == source {
+            continue;
+        }
+        if options.check {
+            unformatted.push(file_path.as_str());
+        } else {
+            fs::write(file_path, formatted)
+                .with_context(|| format!("failed to write {}", file_path))?;
+        }
     }
 
-    write_file(&file_path, binary_vec)?;
+    if !unformatted.is_empty() {
+        return Err(anyhow!("not formatted: {}", unformatted.join(", ")));
+    }
     Ok(())
 }
 
-fn sum(dest: u16, comp: u16, jump: u16) -> u16 {
-    0b1110000000000000 + (comp << 6) + (dest << 3) + jump
+fn disassemble_file(file_path: &str, format: Format) -> Result<()> {
+    let bytes = fs::read(file_path).with_context(|| format!("not find {}", file_path))?;
+    let words = format::decode(&bytes, format).with_context(|| format!("in {}", file_path))?;
+
+    let disassembly = disassembler::disassemble(&words);
+    print!("{}", disassembly.text);
+    for address in disassembly.invalid_addresses.iter() {
+        eprintln!(
+            "warning: ROM[{}] = {:016b} is not a legal Hack instruction",
+            address, words[*address]
+        );
+    }
+
+    Ok(())
 }
 
-fn write_file(file_name: &str, vec: Vec<u16>) -> Result<()> {
-    let output_file_name = file_name.replace(".asm", ".hack");
+fn write_file(file_name: &str, vec: &[u16], format: Format) -> Result<()> {
+    let output_file_name = file_name.replace(".asm", &format!(".{}", format.extension()));
     let mut file = File::create(output_file_name)?;
 
     for binary in vec.iter() {
         println!("{:016b}", binary);
-        file.write_fmt(format_args!("{:016b}\n", binary))?;
     }
+    file.write_all(&format::encode(vec, format))?;
 
     Ok(())
 }
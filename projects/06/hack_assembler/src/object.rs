@@ -0,0 +1,173 @@
+use anyhow::{anyhow, Context, Result};
+
+use crate::diagnostic::Diagnostic;
+
+/// First line of every object file, with the format version.
+const MAGIC: &str = "HACKOBJ 1";
+
+/// One separately assembled module, as written to a `.hobj` file.
+///
+/// Code starts at address 0; the linker moves it to wherever the module ends
+/// up in ROM.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Object {
+    /// File the module was assembled from, for linker errors.
+    pub name: String,
+    pub words: Vec<u16>,
+    /// Global labels other modules may refer to, with their address within
+    /// this module.
+    pub exports: Vec<(String, u16)>,
+    /// Indices of words holding an address within this module.
+    pub relocations: Vec<u16>,
+    /// Indices of words referring to a symbol this module does not define:
+    /// a label exported by another module, or a variable.
+    pub imports: Vec<(u16, String)>,
+    /// Warnings from assembling the module; object files do not keep them.
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// The text form of an object file:
+///
+/// ```text
+/// HACKOBJ 1
+/// export LOOP 0
+/// relocate 3
+/// import 5 counter
+/// code
+/// 0000000000000000
+/// ...
+/// ```
+pub fn write_object(object: &Object) -> String {
+    let mut text = format!("{}\n", MAGIC);
+    for (name, address) in object.exports.iter() {
+        text.push_str(&format!("export {} {}\n", name, address));
+    }
+    for index in object.relocations.iter() {
+        text.push_str(&format!("relocate {}\n", index));
+    }
+    for (index, name) in object.imports.iter() {
+        text.push_str(&format!("import {} {}\n", index, name));
+    }
+    text.push_str("code\n");
+    for word in object.words.iter() {
+        text.push_str(&format!("{:016b}\n", word));
+    }
+    text
+}
+
+/// Reads the text written by `write_object`; `name` is kept for linker errors.
+pub fn parse_object(name: &str, text: &str) -> Result<Object> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, MAGIC)) => {}
+        _ => return Err(anyhow!("{}: not a Hack object file", name)),
+    }
+
+    let mut object = Object {
+        name: name.to_string(),
+        ..Object::default()
+    };
+    let mut in_code = false;
+    for (index, line) in lines {
+        let line = line.trim();
+        let error = || format!("{}:{}: malformed line `{}`", name, index + 1, line);
+        if line.is_empty() {
+            continue;
+        }
+        if in_code {
+            if line.len() != 16 {
+                return Err(anyhow!(error()));
+            }
+            object
+                .words
+                .push(u16::from_str_radix(line, 2).with_context(error)?);
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["export", name, address] => object
+                .exports
+                .push((name.to_string(), address.parse().with_context(error)?)),
+            ["relocate", index] => object.relocations.push(index.parse().with_context(error)?),
+            ["import", index, name] => object
+                .imports
+                .push((index.parse().with_context(error)?, name.to_string())),
+            ["code"] => in_code = true,
+            _ => return Err(anyhow!(error())),
+        }
+    }
+
+    let length = object.words.len();
+    let out_of_range = object
+        .relocations
+        .iter()
+        .chain(object.imports.iter().map(|(index, _)| index))
+        .find(|index| **index as usize >= length);
+    if let Some(index) = out_of_range {
+        return Err(anyhow!(
+            "{}: word {} is outside the {} words of code",
+            name,
+            index,
+            length
+        ));
+    }
+    Ok(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_object, write_object};
+    use crate::Assembler;
+
+    #[test]
+    fn round_trip_object_text() {
+        let source = "(MAIN)\n@counter\nM=M+1\n(.loop)\n@.loop\n0;JMP\n@PRINT\n0;JMP\n";
+        let object = Assembler::new("main.asm").assemble_object(source).unwrap();
+        assert_eq!(object.exports, vec![("MAIN".to_string(), 0)]);
+        assert_eq!(object.relocations, vec![2]);
+        assert_eq!(
+            object.imports,
+            vec![(0, "counter".to_string()), (4, "PRINT".to_string())]
+        );
+
+        let text = write_object(&object);
+        assert!(text.starts_with("HACKOBJ 1\nexport MAIN 0\nrelocate 2\nimport 0 counter\n"));
+        assert_eq!(parse_object("main.asm", &text).unwrap(), object);
+    }
+
+    #[test]
+    fn keep_warnings() {
+        let mut assembler = Assembler::new("main.asm");
+        assembler.set_lint(true);
+        let object = assembler
+            .assemble_object("(MAIN)\n@SP\nAM=M-1;JMP\n")
+            .unwrap();
+        let warnings: Vec<String> = object
+            .warnings
+            .iter()
+            .map(|warning| warning.to_string())
+            .collect();
+        assert_eq!(
+            warnings,
+            vec!["main.asm:3:1: warning: `AM=M-1;JMP` writes A and jumps; the jump goes to the old A, not the value written"]
+        );
+    }
+
+    #[test]
+    fn reject_malformed_objects() {
+        assert!(parse_object("a", "0000000000000000\n").is_err());
+        assert_eq!(
+            parse_object("a", "HACKOBJ 1\nexport X\n")
+                .unwrap_err()
+                .to_string(),
+            "a:2: malformed line `export X`"
+        );
+        assert_eq!(
+            parse_object("a", "HACKOBJ 1\nrelocate 1\ncode\n0000000000000000\n")
+                .unwrap_err()
+                .to_string(),
+            "a: word 1 is outside the 1 words of code"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashSet;
+
+use crate::{
+    code,
+    diagnostic::{Diagnostic, Location},
+    parser::{Command, Field, Line},
+};
+
+/// Why the optimizer removed an instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    /// An A-instruction loading the value A already holds.
+    RedundantLoad,
+    /// Part of `@SP M=M+1` directly followed by `@SP M=M-1`, or the reverse.
+    StackPointerPair,
+    /// An instruction after an unconditional jump that no label leads to.
+    Unreachable,
+}
+
+/// One instruction word the optimizer removed.
+#[derive(Debug, Clone)]
+pub struct Removal {
+    pub reason: Reason,
+    pub location: Location,
+}
+
+/// Summary of the removals, e.g. `saved 5 words: 2 redundant A-loads, ...`.
+pub fn report(removals: &[Removal]) -> String {
+    let count = |reason| {
+        removals
+            .iter()
+            .filter(|removal| removal.reason == reason)
+            .count()
+    };
+    format!(
+        "saved {} words: {} redundant A-loads, {} from SP increment/decrement pairs, {} unreachable",
+        removals.len(),
+        count(Reason::RedundantLoad),
+        count(Reason::StackPointerPair),
+        count(Reason::Unreachable)
+    )
+}
+
+/// Removes instructions that cannot change what the program does, as long
+/// as every jump goes to a label.
+///
+/// Labels are never removed and no rewrite looks across one, so every label
+/// still marks the instruction it was written in front of, and the first
+/// pass gives it the address that instruction ends up at. A jump to a
+/// number, constant or predefined symbol would keep its address while the
+/// code moves, and code after `0;JMP` may be its target, so such a program
+/// is returned unchanged with a warning saying why.
+pub(crate) fn optimize(mut lines: Vec<Line>) -> (Vec<Line>, Vec<Removal>, Option<Diagnostic>) {
+    if let Some(warning) = fixed_address_jump(&lines) {
+        return (lines, vec![], Some(warning));
+    }
+    let mut removals = vec![];
+    // one rewrite can enable another, e.g. a removed SP pair leaves two
+    // loads of the same address next to each other
+    loop {
+        let before = removals.len();
+        lines = optimize_once(lines, &mut removals);
+        if removals.len() == before {
+            return (lines, removals, None);
+        }
+    }
+}
+
+// the first jump through an A-instruction that does not load a label
+fn fixed_address_jump(lines: &[Line]) -> Option<Diagnostic> {
+    let labels: HashSet<&str> = lines
+        .iter()
+        .filter_map(|line| match &line.command {
+            Command::L(token) => Some(token.text.as_str()),
+            _ => None,
+        })
+        .collect();
+    lines.windows(2).find_map(|pair| match pair {
+        [Line {
+            command: Command::A(token),
+            location,
+        }, Line {
+            command: Command::C { jump, .. },
+            ..
+        }] if jump.as_ref().is_some_and(|jump| !jump.text.is_empty()) && !labels.contains(token.text.as_str()) => {
+            Some(Diagnostic::warning(
+                location,
+                token.span.start,
+                format!(
+                    "`@{}` jumps to a fixed ROM address; the optimizer removed nothing because removing instructions would move its target",
+                    token.text
+                ),
+            ))
+        }
+        _ => None,
+    })
+}
+
+fn optimize_once(lines: Vec<Line>, removals: &mut Vec<Removal>) -> Vec<Line> {
+    let mut output: Vec<Line> = Vec::with_capacity(lines.len());
+    let mut remove = |line: Line, reason| {
+        removals.push(Removal {
+            reason,
+            location: line.location,
+        })
+    };
+    // the text of the A-instruction whose value A holds, if known
+    let mut known_a: Option<String> = None;
+    let mut unreachable = false;
+    let mut lines = lines.into_iter();
+
+    while let Some(line) = lines.next() {
+        match &line.command {
+            Command::L(_) => {
+                known_a = None;
+                unreachable = false;
+            }
+            _ if unreachable => {
+                remove(line, Reason::Unreachable);
+                continue;
+            }
+            Command::A(token) => {
+                if known_a.as_deref() == Some(token.text.as_str()) {
+                    remove(line, Reason::RedundantLoad);
+                    continue;
+                }
+                known_a = Some(token.text.clone());
+            }
+            Command::C { dest, comp, jump } => {
+                let step = stack_pointer_step(dest, comp, jump);
+                if step.is_some() && known_a.as_deref() == Some("SP") {
+                    let rest = lines.as_slice();
+                    let cancels = match rest {
+                        [Line {
+                            command: Command::A(token),
+                            ..
+                        }, Line {
+                            command: Command::C { dest, comp, jump },
+                            ..
+                        }, ..]
+                            if token.text == "SP" =>
+                        {
+                            stack_pointer_step(dest, comp, jump)
+                                .map(|other| other == -step.unwrap())
+                                .unwrap_or(false)
+                        }
+                        _ => false,
+                    };
+                    if cancels {
+                        remove(line, Reason::StackPointerPair);
+                        remove(lines.next().unwrap(), Reason::StackPointerPair);
+                        remove(lines.next().unwrap(), Reason::StackPointerPair);
+                        continue;
+                    }
+                }
+                if dest.as_ref().is_some_and(|dest| dest.text.contains('A')) {
+                    known_a = None;
+                }
+                unreachable = jump.as_ref().is_some_and(|jump| jump.text == "JMP");
+            }
+        }
+        output.push(line);
+    }
+
+    output
+}
+
+// +1 for `M=M+1` and -1 for `M=M-1`, in any spelling
+fn stack_pointer_step(dest: &Option<Field>, comp: &Field, jump: &Option<Field>) -> Option<i32> {
+    if dest.as_ref().map(|dest| dest.text.as_str()) != Some("M") || jump.is_some() {
+        return None;
+    }
+    match code::canonical_comp(&comp.text) {
+        Some("M+1") => Some(1),
+        Some("M-1") => Some(-1),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{report, Reason};
+    use crate::{assemble, Assembler};
+
+    fn optimized(source: &str) -> (Vec<u16>, Vec<Reason>) {
+        let mut assembler = Assembler::new("<input>");
+        assembler.set_optimize(true);
+        let program = assembler.assemble(source).unwrap();
+        let reasons = program
+            .removals
+            .iter()
+            .map(|removal| removal.reason)
+            .collect();
+        (program.words, reasons)
+    }
+
+    #[test]
+    fn remove_redundant_instructions() {
+        use Reason::*;
+        let (words, reasons) = optimized(
+            "@SP\nM=M+1\n@SP\nM=M-1\n@SP\nA=M\n@5\nD=A\n@5\nM=D\n@END\n0;JMP\nD=0\n(END)\n@END\n0;JMP\n",
+        );
+        assert_eq!(
+            words,
+            assemble("@SP\nA=M\n@5\nD=A\nM=D\n@END\n0;JMP\n(END)\n@END\n0;JMP\n")
+                .unwrap()
+                .words
+        );
+        assert_eq!(
+            reasons,
+            vec![
+                StackPointerPair,
+                StackPointerPair,
+                StackPointerPair,
+                RedundantLoad,
+                RedundantLoad,
+                Unreachable
+            ]
+        );
+    }
+
+    #[test]
+    fn keep_loads_after_labels_and_writes_to_a() {
+        let source = "@SP\n(LOOP)\n@SP\nAM=M-1\n@SP\nM=M+1\n(NEXT)\n@SP\nM=M-1\n";
+        let (words, reasons) = optimized(source);
+        assert_eq!(words, assemble(source).unwrap().words);
+        assert!(reasons.is_empty());
+    }
+
+    #[test]
+    fn leave_jumps_to_fixed_addresses_alone() {
+        for source in [
+            include_str!("../../max/MaxL.asm"),
+            include_str!("../../rect/RectL.asm"),
+            include_str!("../../pong/PongL.asm"),
+            "@5\nD=A\n@5\nD=D+A\n@6\n0;JMP\n(END)\n@END\n0;JMP\n",
+        ]
+        .iter()
+        {
+            let mut assembler = Assembler::new("<input>");
+            assembler.set_optimize(true);
+            let program = assembler.assemble(source).unwrap();
+            assert_eq!(program.words, assemble(source).unwrap().words);
+            assert!(program.removals.is_empty());
+        }
+
+        let mut assembler = Assembler::new("<input>");
+        assembler.set_optimize(true);
+        let program = assembler.assemble("@1\n@1\n@6\n0;JMP\n").unwrap();
+        assert_eq!(
+            program.warnings[0].to_string(),
+            "<input>:3:2: warning: `@6` jumps to a fixed ROM address; the optimizer removed nothing because removing instructions would move its target"
+        );
+    }
+
+    #[test]
+    fn summarize_removals() {
+        let mut assembler = Assembler::new("<input>");
+        assembler.set_optimize(true);
+        let program = assembler
+            .assemble("(LOOP)\n@LOOP\n@LOOP\n0;JMP\nD=1\n")
+            .unwrap();
+        assert_eq!(
+            report(&program.removals),
+            "saved 2 words: 1 redundant A-loads, 0 from SP increment/decrement pairs, 1 unreachable"
+        );
+    }
+}
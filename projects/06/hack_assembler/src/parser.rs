@@ -1,132 +1,442 @@
-use anyhow::Result;
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    vec::IntoIter,
+use std::vec::IntoIter;
+
+use crate::{
+    diagnostic::{Diagnostic, Location},
+    lexer::{tokenize, Span, SyntaxError, Token, TokenKind},
+    optimizer::{self, Removal},
+    preprocessor::SourceLine,
 };
 
+#[allow(clippy::enum_variant_names)]
 pub enum CommandType {
     ACommand,
     CCommand,
     LCommand,
 }
 
+/// A `dest`, `comp` or `jump` part of a C-instruction.
+#[derive(Debug, Clone)]
+pub(crate) struct Field {
+    pub(crate) text: String,
+    pub(crate) column: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Command {
+    A(Token),
+    L(Token),
+    /// `dest` and `jump` are `None` when the source leaves them out.
+    C {
+        dest: Option<Field>,
+        comp: Field,
+        jump: Option<Field>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Line {
+    pub(crate) command: Command,
+    pub(crate) location: Location,
+}
+
 #[derive(Debug, Clone)]
 pub struct Parser {
-    tokens: IntoIter<String>,
-    current_token: Option<String>,
-    next_token: Option<String>,
+    lines: IntoIter<Line>,
+    current_line: Option<Line>,
+    next_line: Option<Line>,
+    errors: Vec<Diagnostic>,
 }
 
 impl Parser {
-    pub fn new(file: File) -> Result<Parser> {
-        let tokens = BufReader::new(file)
-            .lines()
-            .filter_map(|line| line.ok())
-            .filter(|line| !line.starts_with("//"))
-            .filter(|line| !line.is_empty())
-            .map(pick_out_tokens)
-            .collect::<Vec<String>>()
+    /// Parses `source` as it is, without expanding directives.
+    pub fn new(source: &str) -> Parser {
+        Parser::from_lines(
+            source
+                .lines()
+                .enumerate()
+                .map(|(index, text)| SourceLine {
+                    text: text.to_string(),
+                    location: Location::new("<input>", index + 1),
+                })
+                .collect(),
+        )
+    }
+
+    /// Lines that do not parse are left out of the command stream and
+    /// reported through `errors()`.
+    pub fn from_lines(source_lines: Vec<SourceLine>) -> Parser {
+        let mut errors = vec![];
+        let lines = source_lines
+            .into_iter()
+            .filter_map(|line| match parse_line(&line.text, line.location.line) {
+                Ok(command) => command.map(|command| Line {
+                    command,
+                    location: line.location,
+                }),
+                Err(error) => {
+                    errors.push(Diagnostic::new(&line.location, error.column, error.message));
+                    None
+                }
+            })
+            .collect::<Vec<Line>>()
             .into_iter();
 
         let mut parser = Self {
-            tokens,
-            current_token: None,
-            next_token: None,
+            lines,
+            current_line: None,
+            next_line: None,
+            errors,
         };
 
         // call advance() twice to set the first token and the second token
         parser.advance();
         parser.advance();
 
-        Ok(parser)
+        parser
     }
 
-    pub fn has_more_commands(&self) -> bool {
-        if let None = self.next_token {
-            return false;
-        }
+    pub fn errors(&self) -> &[Diagnostic] {
+        &self.errors
+    }
+
+    /// Runs the peephole optimizer over the commands, which must not have
+    /// been advanced past yet, and returns what it removed, or the warning
+    /// explaining why it removed nothing.
+    pub fn optimize(&mut self) -> (Vec<Removal>, Option<Diagnostic>) {
+        let mut lines: Vec<Line> = self.current_line.take().into_iter().collect();
+        lines.extend(self.next_line.take());
+        lines.extend(&mut self.lines);
+
+        let (lines, removals, warning) = optimizer::optimize(lines);
+        self.lines = lines.into_iter();
+        self.advance();
+        self.advance();
+        (removals, warning)
+    }
 
-        return true;
+    pub fn is_empty(&self) -> bool {
+        self.current_line.is_none()
+    }
+
+    pub fn has_more_commands(&self) -> bool {
+        self.next_line.is_some()
     }
 
     pub fn advance(&mut self) {
-        self.current_token = std::mem::replace(&mut self.next_token, self.tokens.next());
+        self.current_line = std::mem::replace(&mut self.next_line, self.lines.next());
     }
 
     pub fn command_type(&self) -> CommandType {
-        let token = self.current_token.as_ref().unwrap();
-
-        if is_a_command(&token) {
-            CommandType::ACommand
-        } else if is_l_command(&token) {
-            CommandType::LCommand
-        } else {
-            CommandType::CCommand
+        match self.current_command() {
+            Command::A(_) => CommandType::ACommand,
+            Command::L(_) => CommandType::LCommand,
+            Command::C { .. } => CommandType::CCommand,
         }
     }
 
     pub fn symbol(&self) -> String {
-        let mut token = self.current_token.as_ref().unwrap().to_string();
-
-        if is_a_command(&token) {
-            token.retain(|c| c != '@');
-            token
-        } else {
-            token.retain(|c| c != '(');
-            token.retain(|c| c != ')');
-            token
+        match self.current_command() {
+            Command::A(token) | Command::L(token) => token.text.clone(),
+            Command::C { .. } => panic!("symbol() called on a C-instruction"),
         }
     }
 
+    /// `null` when the instruction has no dest.
     pub fn dest(&self) -> String {
-        let current_token = self.current_token.as_ref().unwrap();
-        if let Some(index) = current_token.find("=") {
-            return current_token[..index].to_string();
-        }
-
-        return String::from("null");
+        self.fields()
+            .0
+            .map_or(String::from("null"), |field| field.text.clone())
     }
 
     pub fn comp(&self) -> String {
-        let current_token = self.current_token.as_ref().unwrap();
-        match current_token.find("=") {
-            Some(equal_index) => match current_token.find(";") {
-                Some(semicolon_index) => {
-                    current_token[equal_index+1..semicolon_index].to_string()
-                }
-                None => current_token[equal_index+1..].to_string(),
-            },
-            None => match current_token.find(";") {
-                Some(semicolon_index) => current_token[..semicolon_index].to_string(),
-                None => current_token.to_string(),
-            },
-        }
+        self.fields().1.text.clone()
     }
 
+    /// `null` when the instruction has no jump.
     pub fn jump(&self) -> String {
-        let current_token = self.current_token.as_ref().unwrap();
-        if let Some(index) = current_token.find(";") {
-            return current_token[..index].to_string();
+        self.fields()
+            .2
+            .map_or(String::from("null"), |field| field.text.clone())
+    }
+
+    /// Whether the source writes a dest, so a `null` typed in it can be told
+    /// apart from an absent one.
+    pub fn has_dest(&self) -> bool {
+        self.fields().0.is_some()
+    }
+
+    pub fn has_jump(&self) -> bool {
+        self.fields().2.is_some()
+    }
+
+    /// 1-based line number of the current command in the source file.
+    pub fn line_number(&self) -> usize {
+        self.location().line
+    }
+
+    pub fn location(&self) -> &Location {
+        &self.current_line.as_ref().unwrap().location
+    }
+
+    pub fn symbol_column(&self) -> usize {
+        match self.current_command() {
+            Command::A(token) | Command::L(token) => token.span.start,
+            Command::C { .. } => panic!("symbol_column() called on a C-instruction"),
+        }
+    }
+
+    /// Absent dest and jump fields point at the start of the instruction.
+    pub fn dest_column(&self) -> usize {
+        let (dest, comp, _) = self.fields();
+        dest.unwrap_or(comp).column
+    }
+
+    pub fn comp_column(&self) -> usize {
+        self.fields().1.column
+    }
+
+    pub fn jump_column(&self) -> usize {
+        let (dest, comp, jump) = self.fields();
+        jump.or(dest).unwrap_or(comp).column
+    }
+
+    fn current_command(&self) -> &Command {
+        &self.current_line.as_ref().unwrap().command
+    }
+
+    fn fields(&self) -> (Option<&Field>, &Field, Option<&Field>) {
+        match self.current_command() {
+            Command::C { dest, comp, jump } => (dest.as_ref(), comp, jump.as_ref()),
+            _ => panic!("dest/comp/jump called on an A- or L-command"),
         }
+    }
+}
+
+fn syntax_error(line_number: usize, column: usize, message: &str) -> SyntaxError {
+    SyntaxError {
+        line: line_number,
+        column,
+        message: message.to_string(),
+    }
+}
+
+// `None` for lines holding nothing but whitespace and comments
+pub(crate) fn parse_line(line: &str, line_number: usize) -> Result<Option<Command>, SyntaxError> {
+    let tokens = tokenize(line, line_number)?;
+    let first = match tokens.first() {
+        Some(first) => first,
+        None => return Ok(None),
+    };
+    let line_end = tokens.last().unwrap().span.end;
 
-        return String::from("null");
+    match first.kind {
+        TokenKind::At => match tokens.get(1) {
+            Some(value)
+                if matches!(
+                    value.kind,
+                    TokenKind::Symbol | TokenKind::Number | TokenKind::Char
+                ) =>
+            {
+                if let Some(extra) = tokens.get(2) {
+                    return Err(syntax_error(
+                        line_number,
+                        extra.span.start,
+                        &format!("unexpected `{}` after A-instruction", extra.text),
+                    ));
+                }
+                Ok(Some(Command::A(value.clone())))
+            }
+            Some(other) => Err(syntax_error(
+                line_number,
+                other.span.start,
+                &format!(
+                    "expected a symbol or number after `@`, found `{}`",
+                    other.text
+                ),
+            )),
+            None => Err(syntax_error(
+                line_number,
+                line_end,
+                "expected a symbol or number after `@`",
+            )),
+        },
+        TokenKind::LParen => {
+            let symbol = match tokens.get(1) {
+                Some(symbol) if symbol.kind == TokenKind::Symbol => symbol,
+                Some(other) => {
+                    return Err(syntax_error(
+                        line_number,
+                        other.span.start,
+                        &format!("expected a label name after `(`, found `{}`", other.text),
+                    ))
+                }
+                None => return Err(syntax_error(line_number, line_end, "unclosed `(`")),
+            };
+            match tokens.get(2) {
+                Some(close) if close.kind == TokenKind::RParen => {}
+                Some(other) => {
+                    return Err(syntax_error(
+                        line_number,
+                        other.span.start,
+                        &format!("expected `)` after label name, found `{}`", other.text),
+                    ))
+                }
+                None => return Err(syntax_error(line_number, line_end, "unclosed `(`")),
+            }
+            if let Some(extra) = tokens.get(3) {
+                return Err(syntax_error(
+                    line_number,
+                    extra.span.start,
+                    &format!("unexpected `{}` after label", extra.text),
+                ));
+            }
+            Ok(Some(Command::L(symbol.clone())))
+        }
+        _ => parse_c_instruction(&tokens, line_number).map(Some),
     }
 }
 
-fn is_a_command(token: &String) -> bool {
-    token.starts_with("@")
+fn parse_c_instruction(tokens: &[Token], line_number: usize) -> Result<Command, SyntaxError> {
+    let line_end = tokens.last().unwrap().span.end;
+    let mut equals: Option<usize> = None;
+    let mut semicolon: Option<usize> = None;
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::Equals if equals.is_some() => {
+                return Err(syntax_error(
+                    line_number,
+                    token.span.start,
+                    "more than one `=` in C-instruction",
+                ))
+            }
+            TokenKind::Equals if semicolon.is_some() => {
+                return Err(syntax_error(
+                    line_number,
+                    token.span.start,
+                    "`=` after `;` in C-instruction",
+                ))
+            }
+            TokenKind::Equals => equals = Some(index),
+            TokenKind::Semicolon if semicolon.is_some() => {
+                return Err(syntax_error(
+                    line_number,
+                    token.span.start,
+                    "more than one `;` in C-instruction",
+                ))
+            }
+            TokenKind::Semicolon => semicolon = Some(index),
+            TokenKind::At
+            | TokenKind::LParen
+            | TokenKind::RParen
+            | TokenKind::Char
+            | TokenKind::String
+            | TokenKind::Comma
+            | TokenKind::Comparison => {
+                return Err(syntax_error(
+                    line_number,
+                    token.span.start,
+                    &format!("unexpected `{}` in C-instruction", token.text),
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    let comp_start = equals.map_or(0, |index| index + 1);
+    let comp_end = semicolon.unwrap_or(tokens.len());
+
+    let dest = match equals {
+        Some(index) => Some(single_token_field(
+            &tokens[..index],
+            tokens[index].span,
+            line_number,
+            "dest",
+        )?),
+        None => None,
+    };
+    let comp = Field {
+        text: tokens[comp_start..comp_end]
+            .iter()
+            .map(|token| token.text.as_str())
+            .collect(),
+        column: if comp_start < comp_end {
+            tokens[comp_start].span.start
+        } else {
+            tokens
+                .get(comp_end)
+                .map_or(line_end, |token| token.span.start)
+        },
+    };
+    let jump = match semicolon {
+        Some(index) => Some(single_token_field(
+            &tokens[index + 1..],
+            tokens[index].span,
+            line_number,
+            "jump",
+        )?),
+        None => None,
+    };
+
+    Ok(Command::C { dest, comp, jump })
 }
 
-fn is_l_command(token: &String) -> bool {
-    token.starts_with("(")
+// dest and jump are a single word; an empty one is kept so that the
+// assembler reports it as an unknown mnemonic
+fn single_token_field(
+    tokens: &[Token],
+    separator: Span,
+    line_number: usize,
+    name: &str,
+) -> Result<Field, SyntaxError> {
+    match tokens {
+        [] => Ok(Field {
+            text: String::new(),
+            column: separator.end,
+        }),
+        [token] => Ok(Field {
+            text: token.text.clone(),
+            column: token.span.start,
+        }),
+        [_, extra, ..] => Err(syntax_error(
+            line_number,
+            extra.span.start,
+            &format!("unexpected `{}` in {}", extra.text, name),
+        )),
+    }
 }
 
-fn pick_out_tokens(mut line: String) -> String {
-    line.retain(|c| !c.is_whitespace());
-    if let Some(index) = line.find("//") {
-        return line[..index].to_string();
-    } else {
-        return line;
+#[cfg(test)]
+mod tests {
+    use super::{CommandType, Parser};
+
+    #[test]
+    fn parse_c_instruction_fields() {
+        let parser = Parser::new("  AM = M - 1 ; JNE // decrement\n");
+        assert!(matches!(parser.command_type(), CommandType::CCommand));
+        assert_eq!(parser.dest(), "AM");
+        assert_eq!(parser.comp(), "M-1");
+        assert_eq!(parser.jump(), "JNE");
+        assert_eq!(parser.line_number(), 1);
+        assert_eq!(parser.comp_column(), 8);
+        assert_eq!(parser.jump_column(), 16);
+    }
+
+    #[test]
+    fn report_malformed_lines() {
+        let parser = Parser::new("@my label\n(LOOP // x)\nD=M=1\n@1st\n(LOOP\n@R0\n");
+        let errors: Vec<String> = parser.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "<input>:1:5: error: unexpected `label` after A-instruction",
+                "<input>:2:6: error: unclosed `(`",
+                "<input>:3:4: error: more than one `=` in C-instruction",
+                "<input>:4:2: error: symbol `1st` cannot start with a digit",
+                "<input>:5:6: error: unclosed `(`",
+            ]
+        );
+        assert_eq!(parser.symbol(), "R0");
+        assert!(!parser.has_more_commands());
     }
 }
@@ -0,0 +1,849 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    code,
+    diagnostic::{Diagnostic, Expansion, Location},
+    lexer::{tokenize, Token, TokenKind},
+    symbol_table::PREDEFINED_SYMBOLS,
+};
+
+/// Macro calls may expand to further macro calls, but not endlessly.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// Start of the names given to labels declared in a macro body.
+pub const MACRO_LABEL_PREFIX: &str = "__";
+/// File name of the location given to constants defined with `define`.
+const COMMAND_LINE: &str = "<command line>";
+
+/// One line of assembly after preprocessing, with where it came from.
+#[derive(Debug, Clone)]
+pub struct SourceLine {
+    pub text: String,
+    pub location: Location,
+}
+
+/// A named value from `.equ` or a command-line define. It takes up neither
+/// RAM nor ROM.
+#[derive(Debug, Clone)]
+pub struct Constant {
+    pub name: String,
+    pub value: u16,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<SourceLine>,
+    /// Labels declared in the body, renamed for every expansion.
+    labels: Vec<String>,
+    location: Location,
+}
+
+/// An open `.if` block.
+struct Condition {
+    /// Whether the lines of the current branch are kept.
+    active: bool,
+    /// Whether any branch so far was kept, so `.else` knows what to do.
+    taken: bool,
+    /// Whether the block itself sits in kept lines.
+    enclosing_active: bool,
+    has_else: bool,
+    location: Location,
+}
+
+/// Expands the directives of the assembly source before the two passes see it.
+///
+/// A macro is defined with
+///
+/// ```text
+/// .macro NAME param1, param2
+///     ...
+/// .endm
+/// ```
+///
+/// and called as `NAME arg1, arg2`. Parameters are replaced by the argument
+/// text wherever they appear as a symbol in the body, and labels declared in
+/// the body get a unique name per expansion so a macro can be used twice.
+///
+/// `.include "file.asm"` splices in another file, resolved relative to the
+/// file containing the directive.
+///
+/// `.equ NAME value` names a number, a character or another constant.
+///
+/// `.if VALUE [OP VALUE]`, `.ifdef NAME` and `.ifndef NAME` keep the lines up
+/// to the matching `.else` or `.endif` only if the condition holds. `OP` is
+/// one of `== != < <= > >=`, and a lone value holds when it is not zero.
+/// Blocks nest, but must be closed in the file or macro that opened them.
+pub struct Preprocessor {
+    file_name: String,
+    constants: Vec<Constant>,
+    macros: HashMap<String, Macro>,
+    expansion_count: usize,
+    /// Files currently being included, outermost first, to detect cycles.
+    include_stack: Vec<(PathBuf, String)>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Preprocessor {
+    pub fn new(file_name: &str) -> Self {
+        Preprocessor {
+            file_name: file_name.to_string(),
+            constants: vec![],
+            macros: HashMap::new(),
+            expansion_count: 0,
+            include_stack: vec![],
+            errors: vec![],
+        }
+    }
+
+    pub fn errors(&self) -> &[Diagnostic] {
+        &self.errors
+    }
+
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+    /// Defines a constant as if by `.equ`, e.g. from `-D NAME=value`. It
+    /// takes precedence over a `.equ` of the same name in the source, so the
+    /// same source can be assembled with different values.
+    pub fn define(&mut self, name: &str, value: u16) {
+        self.constants.push(Constant {
+            name: name.to_string(),
+            value,
+            location: Location::new(COMMAND_LINE, 0),
+        });
+    }
+
+    pub fn process(&mut self, source: &str) -> Vec<SourceLine> {
+        if let Ok(path) = fs::canonicalize(&self.file_name) {
+            self.include_stack.push((path, self.file_name.clone()));
+        }
+
+        let lines = source
+            .lines()
+            .enumerate()
+            .map(|(index, text)| SourceLine {
+                text: text.to_string(),
+                location: Location::new(&self.file_name, index + 1),
+            })
+            .collect();
+
+        let mut output = vec![];
+        self.process_lines(lines, &mut output, 0);
+        output
+    }
+
+    fn process_lines(
+        &mut self,
+        lines: Vec<SourceLine>,
+        output: &mut Vec<SourceLine>,
+        depth: usize,
+    ) {
+        let mut lines = lines.into_iter();
+        let mut conditions: Vec<Condition> = vec![];
+
+        while let Some(line) = lines.next() {
+            let skipping = conditions.iter().any(|condition| !condition.active);
+
+            // lines that do not tokenize are left for the parser to report
+            let tokens = match tokenize(&line.text, line.location.line) {
+                Ok(tokens) => tokens,
+                Err(_) => {
+                    if !skipping {
+                        output.push(line);
+                    }
+                    continue;
+                }
+            };
+            let first = match tokens.first() {
+                Some(first) if first.kind == TokenKind::Symbol => first,
+                _ => {
+                    if !skipping {
+                        output.push(line);
+                    }
+                    continue;
+                }
+            };
+
+            match first.text.as_str() {
+                ".if" | ".ifdef" | ".ifndef" => {
+                    // conditions in skipped lines may name undefined symbols
+                    let active = !skipping && self.condition(&line, &tokens);
+                    conditions.push(Condition {
+                        active,
+                        taken: active,
+                        enclosing_active: !skipping,
+                        has_else: false,
+                        location: line.location.clone(),
+                    });
+                }
+                ".else" => match conditions.last_mut() {
+                    Some(condition) if !condition.has_else => {
+                        condition.has_else = true;
+                        condition.active = condition.enclosing_active && !condition.taken;
+                        condition.taken = true;
+                    }
+                    Some(_) => {
+                        self.error(&line.location, first.span.start, "more than one `.else`")
+                    }
+                    None => self.error(&line.location, first.span.start, "`.else` without `.if`"),
+                },
+                ".endif" => {
+                    if conditions.pop().is_none() {
+                        self.error(&line.location, first.span.start, "`.endif` without `.if`");
+                    }
+                }
+                _ if skipping => {}
+                ".macro" => self.define_macro(&line, &tokens, &mut lines),
+                ".endm" => self.error(&line.location, first.span.start, "`.endm` without `.macro`"),
+                ".include" => self.include(&line, &tokens, output, depth),
+                ".equ" => self.define_constant(&line, &tokens),
+                name if self.macros.contains_key(name) && !is_c_instruction(&tokens) => {
+                    if depth == MAX_EXPANSION_DEPTH {
+                        self.error(
+                            &line.location,
+                            first.span.start,
+                            &format!("macro `{}` expands recursively", name),
+                        );
+                        continue;
+                    }
+                    let expanded = self.expand_macro(&line, &tokens);
+                    self.process_lines(expanded, output, depth + 1);
+                }
+                directive if directive.starts_with('.') => self.error(
+                    &line.location,
+                    first.span.start,
+                    &format!("unknown directive `{}`", directive),
+                ),
+                _ => output.push(line),
+            }
+        }
+
+        for condition in conditions {
+            self.error(&condition.location, 1, "`.if` has no `.endif`");
+        }
+    }
+
+    // whether a `.if`, `.ifdef` or `.ifndef` line holds; malformed conditions
+    // are reported and do not hold
+    fn condition(&mut self, line: &SourceLine, tokens: &[Token]) -> bool {
+        let directive = tokens[0].text.as_str();
+        if directive != ".if" {
+            return match tokens {
+                [_, name] if name.kind == TokenKind::Symbol => {
+                    let defined = self
+                        .constants
+                        .iter()
+                        .any(|constant| constant.name == name.text);
+                    defined == (directive == ".ifdef")
+                }
+                _ => {
+                    let column = tokens
+                        .get(1)
+                        .map_or(tokens[0].span.end, |token| token.span.start);
+                    self.error(
+                        &line.location,
+                        column,
+                        &format!("expected `{} NAME`", directive),
+                    );
+                    false
+                }
+            };
+        }
+
+        let (left, comparison, right) = match tokens {
+            [_, value] => (value, None, None),
+            [_, left, comparison, right] if comparison.kind == TokenKind::Comparison => {
+                (left, Some(comparison), Some(right))
+            }
+            _ => {
+                let column = tokens
+                    .get(1)
+                    .map_or(tokens[0].span.end, |token| token.span.start);
+                self.error(
+                    &line.location,
+                    column,
+                    "expected `.if VALUE` or `.if VALUE OP VALUE`",
+                );
+                return false;
+            }
+        };
+
+        let mut operand = |token: &Token| match self.evaluate(token) {
+            Ok(value) => Some(value),
+            Err(message) => {
+                self.error(&line.location, token.span.start, &message);
+                None
+            }
+        };
+        let left = operand(left);
+        let right = right.map(&mut operand);
+        match (left, comparison, right) {
+            (Some(value), None, None) => value != 0,
+            (Some(left), Some(comparison), Some(Some(right))) => match comparison.text.as_str() {
+                "==" => left == right,
+                "!=" => left != right,
+                "<" => left < right,
+                "<=" => left <= right,
+                ">" => left > right,
+                _ => left >= right,
+            },
+            _ => false,
+        }
+    }
+
+    fn define_macro(
+        &mut self,
+        line: &SourceLine,
+        tokens: &[Token],
+        lines: &mut impl Iterator<Item = SourceLine>,
+    ) {
+        let name = match tokens.get(1) {
+            Some(name) if name.kind == TokenKind::Symbol && !name.text.starts_with('.') => name,
+            _ => {
+                self.error(
+                    &line.location,
+                    tokens[0].span.end,
+                    "expected a macro name after `.macro`",
+                );
+                skip_macro_body(lines);
+                return;
+            }
+        };
+
+        let mut params = vec![];
+        for arg in split_arguments(&tokens[2..]) {
+            match arg {
+                [param] if param.kind == TokenKind::Symbol => params.push(param.text.clone()),
+                _ => {
+                    let column = arg.first().map_or(name.span.end, |token| token.span.start);
+                    self.error(&line.location, column, "macro parameters must be symbols");
+                }
+            }
+        }
+
+        let mut body = vec![];
+        let mut terminated = false;
+        for body_line in lines.by_ref() {
+            match first_symbol(&body_line.text) {
+                Some(directive) if directive == ".endm" => {
+                    terminated = true;
+                    break;
+                }
+                Some(directive) if directive == ".macro" => {
+                    self.error(&body_line.location, 1, "macro definitions cannot be nested")
+                }
+                _ => body.push(body_line),
+            }
+        }
+        if !terminated {
+            self.error(
+                &line.location,
+                tokens[0].span.start,
+                &format!("macro `{}` has no `.endm`", name.text),
+            );
+            return;
+        }
+
+        if let Some(previous) = self.macros.get(&name.text) {
+            let message = format!(
+                "macro `{}` is already defined at {}:{}",
+                name.text, previous.location.file_name, previous.location.line
+            );
+            self.error(&line.location, name.span.start, &message);
+            return;
+        }
+
+        let labels = body
+            .iter()
+            .filter_map(|body_line| match tokenize(&body_line.text, 0).as_deref() {
+                Ok([open, label, close])
+                    if open.kind == TokenKind::LParen && close.kind == TokenKind::RParen =>
+                {
+                    Some(label.text.clone())
+                }
+                _ => None,
+            })
+            .collect();
+
+        self.macros.insert(
+            name.text.clone(),
+            Macro {
+                params,
+                body,
+                labels,
+                location: line.location.clone(),
+            },
+        );
+    }
+
+    fn define_constant(&mut self, line: &SourceLine, tokens: &[Token]) {
+        let (name, value) = match tokens {
+            [_, name, value] if name.kind == TokenKind::Symbol => (name, value),
+            _ => {
+                let column = tokens
+                    .get(1)
+                    .map_or(tokens[0].span.end, |token| token.span.start);
+                self.error(&line.location, column, "expected `.equ NAME value`");
+                return;
+            }
+        };
+
+        if let Some(previous) = self
+            .constants
+            .iter()
+            .find(|constant| constant.name == name.text)
+        {
+            if previous.location.file_name == COMMAND_LINE {
+                return;
+            }
+            let message = format!(
+                "constant `{}` is already defined at {}:{}",
+                name.text, previous.location.file_name, previous.location.line
+            );
+            self.error(&line.location, name.span.start, &message);
+            return;
+        }
+        if PREDEFINED_SYMBOLS
+            .iter()
+            .any(|(symbol, _)| *symbol == name.text)
+        {
+            let message = format!("`{}` is a predefined symbol", name.text);
+            self.error(&line.location, name.span.start, &message);
+            return;
+        }
+
+        match self.evaluate(value) {
+            Ok(value) => self.constants.push(Constant {
+                name: name.text.clone(),
+                value,
+                location: line.location.clone(),
+            }),
+            Err(message) => self.error(&line.location, value.span.start, &message),
+        }
+    }
+
+    // the value of a number, character, constant or predefined symbol
+    fn evaluate(&self, token: &Token) -> Result<u16, String> {
+        if let Some(value) = code::constant(&token.text) {
+            return value;
+        }
+        if let Some(constant) = self
+            .constants
+            .iter()
+            .find(|constant| constant.name == token.text)
+        {
+            return Ok(constant.value);
+        }
+        if let Some((_, address)) = PREDEFINED_SYMBOLS
+            .iter()
+            .find(|(symbol, _)| *symbol == token.text)
+        {
+            return Ok(*address);
+        }
+        Err(format!(
+            "`{}` is not a number or a defined constant",
+            token.text
+        ))
+    }
+
+    fn include(
+        &mut self,
+        line: &SourceLine,
+        tokens: &[Token],
+        output: &mut Vec<SourceLine>,
+        depth: usize,
+    ) {
+        let name = match tokens {
+            [_, name] if name.kind == TokenKind::String => &name.text[1..name.text.len() - 1],
+            _ => {
+                let column = tokens
+                    .get(1)
+                    .map_or(tokens[0].span.end, |token| token.span.start);
+                self.error(
+                    &line.location,
+                    column,
+                    "expected a quoted file name after `.include`",
+                );
+                return;
+            }
+        };
+        let column = tokens[1].span.start;
+
+        let path = Path::new(&line.location.file_name)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(name);
+        let display_name = path.display().to_string();
+        let source = match fs::canonicalize(&path)
+            .and_then(|canonical| fs::read_to_string(&canonical).map(|source| (canonical, source)))
+        {
+            Ok((canonical, source)) => {
+                if self
+                    .include_stack
+                    .iter()
+                    .any(|(open, _)| *open == canonical)
+                {
+                    let mut chain: Vec<&str> = self
+                        .include_stack
+                        .iter()
+                        .map(|(_, name)| name.as_str())
+                        .collect();
+                    chain.push(&display_name);
+                    let message = format!("include cycle: {}", chain.join(" -> "));
+                    self.error(&line.location, column, &message);
+                    return;
+                }
+                self.include_stack.push((canonical, display_name.clone()));
+                source
+            }
+            Err(error) => {
+                self.error(
+                    &line.location,
+                    column,
+                    &format!("cannot read `{}`: {}", display_name, error),
+                );
+                return;
+            }
+        };
+
+        let expansion = Expansion {
+            description: String::from("included"),
+            file_name: line.location.file_name.clone(),
+            line: line.location.line,
+        };
+        let lines = source
+            .lines()
+            .enumerate()
+            .map(|(index, text)| SourceLine {
+                text: text.to_string(),
+                location: Location::new(&display_name, index + 1)
+                    .expanded(expansion.clone(), &line.location),
+            })
+            .collect();
+        self.process_lines(lines, output, depth);
+        self.include_stack.pop();
+    }
+
+    fn expand_macro(&mut self, call: &SourceLine, tokens: &[Token]) -> Vec<SourceLine> {
+        let name = &tokens[0].text;
+        let definition = self.macros[name].clone();
+        let args: Vec<String> = split_arguments(&tokens[1..])
+            .iter()
+            .map(|arg| arg.iter().map(|token| token.text.as_str()).collect())
+            .collect();
+
+        if args.len() != definition.params.len() || args.iter().any(|arg| arg.is_empty()) {
+            self.error(
+                &call.location,
+                tokens[0].span.start,
+                &format!(
+                    "macro `{}` takes {} argument(s) but {} were given",
+                    name,
+                    definition.params.len(),
+                    args.iter().filter(|arg| !arg.is_empty()).count()
+                ),
+            );
+            return vec![];
+        }
+
+        self.expansion_count += 1;
+        let expansion_count = self.expansion_count;
+        let expansion = Expansion {
+            description: format!("in expansion of macro `{}`", name),
+            file_name: call.location.file_name.clone(),
+            line: call.location.line,
+        };
+
+        definition
+            .body
+            .iter()
+            .map(|body_line| {
+                let text = substitute(&body_line.text, |symbol| {
+                    if let Some(index) = definition.params.iter().position(|param| param == symbol)
+                    {
+                        Some(args[index].clone())
+                    } else if definition.labels.iter().any(|label| label == symbol) {
+                        Some(format!(
+                            "{}{}_{}_{}",
+                            MACRO_LABEL_PREFIX, name, expansion_count, symbol
+                        ))
+                    } else {
+                        None
+                    }
+                });
+                SourceLine {
+                    text,
+                    location: body_line
+                        .location
+                        .expanded(expansion.clone(), &call.location),
+                }
+            })
+            .collect()
+    }
+
+    fn error(&mut self, location: &Location, column: usize, message: &str) {
+        self.errors
+            .push(Diagnostic::new(location, column, message.to_string()));
+    }
+}
+
+// `NAME D;JMP` cannot be a macro call, so a macro named like a register
+// does not swallow ordinary instructions
+fn is_c_instruction(tokens: &[Token]) -> bool {
+    tokens
+        .iter()
+        .any(|token| matches!(token.kind, TokenKind::Equals | TokenKind::Semicolon))
+}
+
+fn first_symbol(text: &str) -> Option<String> {
+    match tokenize(text, 0).ok()?.first() {
+        Some(token) if token.kind == TokenKind::Symbol => Some(token.text.clone()),
+        _ => None,
+    }
+}
+
+fn skip_macro_body(lines: &mut impl Iterator<Item = SourceLine>) {
+    for line in lines {
+        if first_symbol(&line.text).as_deref() == Some(".endm") {
+            break;
+        }
+    }
+}
+
+/// Comma separated groups of tokens; no tokens at all is no arguments.
+pub fn split_arguments(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return vec![];
+    }
+    tokens
+        .split(|token| token.kind == TokenKind::Comma)
+        .collect()
+}
+
+/// Rewrites the symbols of `text` for which `replace` returns a new spelling,
+/// leaving everything else (including comments) in place.
+pub fn substitute(text: &str, mut replace: impl FnMut(&str) -> Option<String>) -> String {
+    let tokens = match tokenize(text, 0) {
+        Ok(tokens) => tokens,
+        Err(_) => return text.to_string(),
+    };
+
+    let mut chars: Vec<char> = text.chars().collect();
+    for token in tokens.iter().rev() {
+        if token.kind != TokenKind::Symbol {
+            continue;
+        }
+        if let Some(replacement) = replace(&token.text) {
+            chars.splice(
+                token.span.start - 1..token.span.end - 1,
+                replacement.chars(),
+            );
+        }
+    }
+    chars.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Preprocessor;
+
+    fn texts(source: &str) -> Vec<String> {
+        let mut preprocessor = Preprocessor::new("<input>");
+        let lines = preprocessor.process(source);
+        assert!(
+            preprocessor.errors().is_empty(),
+            "{:?}",
+            preprocessor.errors()
+        );
+        lines
+            .into_iter()
+            .map(|line| line.text.trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn expand_parameters_and_local_labels() {
+        let source = "\
+.macro JUMP_IF_ZERO addr, target
+    @addr
+    D=M
+    @target
+    D;JEQ
+.endm
+.macro SKIP
+    @skip
+    0;JMP
+(skip)
+.endm
+JUMP_IF_ZERO R1, END
+SKIP
+SKIP
+";
+        assert_eq!(
+            texts(source),
+            vec![
+                "@R1",
+                "D=M",
+                "@END",
+                "D;JEQ",
+                "@__SKIP_2_skip",
+                "0;JMP",
+                "(__SKIP_2_skip)",
+                "@__SKIP_3_skip",
+                "0;JMP",
+                "(__SKIP_3_skip)",
+            ]
+        );
+    }
+
+    #[test]
+    fn expanded_lines_point_at_the_call_site() {
+        let mut preprocessor = Preprocessor::new("<input>");
+        let lines = preprocessor.process(".macro INC\nM=M+1\n.endm\n@R0\nINC\n");
+        assert_eq!(lines[1].location.line, 2);
+        assert_eq!(lines[1].location.root_line(), 5);
+    }
+
+    #[test]
+    fn include_files_relative_to_the_includer() {
+        let dir = std::env::temp_dir().join(format!("hack_include_{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("main.asm"), ".include \"lib/inc.asm\"\n@R0\n").unwrap();
+        fs::write(dir.join("lib/inc.asm"), "M=M+1\n.include \"loop.asm\"\n").unwrap();
+        fs::write(dir.join("lib/loop.asm"), ".include \"inc.asm\"\n").unwrap();
+
+        let main = dir.join("main.asm").display().to_string();
+        let mut preprocessor = Preprocessor::new(&main);
+        let lines = preprocessor.process(&fs::read_to_string(&main).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(lines[0].text, "M=M+1");
+        assert_eq!(lines[0].location.root_line(), 1);
+        assert_eq!(lines[1].text, "@R0");
+
+        let error = preprocessor.errors()[0].to_string();
+        assert!(error.contains("include cycle:"), "{}", error);
+        assert!(
+            error.ends_with(&format!("\n    included at {}:1", main)),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn define_constants() {
+        let mut preprocessor = Preprocessor::new("<input>");
+        preprocessor.define("WIDTH", 32);
+        let lines = preprocessor.process(".equ SIZE 8192\n.equ BASE SCREEN\n.equ W WIDTH\n@SIZE\n");
+        assert_eq!(lines.len(), 1);
+        let constants: Vec<(&str, u16)> = preprocessor
+            .constants()
+            .iter()
+            .map(|constant| (constant.name.as_str(), constant.value))
+            .collect();
+        assert_eq!(
+            constants,
+            vec![("WIDTH", 32), ("SIZE", 8192), ("BASE", 16384), ("W", 32)]
+        );
+
+        preprocessor.process(".equ WIDTH 64\n.equ KBD 1\n.equ X Y\n.equ SIZE 1\n");
+        let errors: Vec<String> = preprocessor
+            .errors()
+            .iter()
+            .map(|error| error.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "<input>:2:6: error: `KBD` is a predefined symbol",
+                "<input>:3:8: error: `Y` is not a number or a defined constant",
+                "<input>:4:6: error: constant `SIZE` is already defined at <input>:1",
+            ]
+        );
+        // the command line overrides `.equ`
+        assert_eq!(preprocessor.constants()[0].value, 32);
+    }
+
+    #[test]
+    fn assemble_conditionally() {
+        let source = "\
+.equ LEVEL 2
+.if LEVEL >= 2
+    @verbose
+    .ifdef RELEASE
+        @release
+    .else
+        @debug
+    .endif
+.else
+    @quiet
+    .if UNDEFINED
+    .endif
+.endif
+.ifndef RELEASE
+    @end
+.endif
+";
+        let mut preprocessor = Preprocessor::new("<input>");
+        let lines = preprocessor.process(source);
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.trim()).collect();
+        assert_eq!(texts, vec!["@verbose", "@debug", "@end"]);
+        assert!(preprocessor.errors().is_empty());
+
+        let mut preprocessor = Preprocessor::new("<input>");
+        preprocessor.define("RELEASE", 1);
+        let lines = preprocessor.process(source);
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.trim()).collect();
+        assert_eq!(texts, vec!["@verbose", "@release"]);
+    }
+
+    #[test]
+    fn report_unbalanced_conditions() {
+        let mut preprocessor = Preprocessor::new("<input>");
+        preprocessor.process(".if X\n.else\n.else\n.endif\n.endif\n.if 1 2\n");
+        let errors: Vec<String> = preprocessor
+            .errors()
+            .iter()
+            .map(|error| error.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "<input>:1:5: error: `X` is not a number or a defined constant",
+                "<input>:3:1: error: more than one `.else`",
+                "<input>:5:1: error: `.endif` without `.if`",
+                "<input>:6:5: error: expected `.if VALUE` or `.if VALUE OP VALUE`",
+                "<input>:6:1: error: `.if` has no `.endif`",
+            ]
+        );
+    }
+
+    #[test]
+    fn report_malformed_macros() {
+        let mut preprocessor = Preprocessor::new("<input>");
+        preprocessor.process(".macro ONE x\n@x\n.endm\nONE\n.endm\n.foo\n.macro OPEN\n");
+        let errors: Vec<String> = preprocessor
+            .errors()
+            .iter()
+            .map(|error| error.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "<input>:4:1: error: macro `ONE` takes 1 argument(s) but 0 were given",
+                "<input>:5:1: error: `.endm` without `.macro`",
+                "<input>:6:1: error: unknown directive `.foo`",
+                "<input>:7:1: error: macro `OPEN` has no `.endm`",
+            ]
+        );
+    }
+}
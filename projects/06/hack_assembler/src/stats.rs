@@ -0,0 +1,275 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    instruction::Instruction, preprocessor::MACRO_LABEL_PREFIX, symbol_map::json_string,
+    DefinitionKind, Program,
+};
+
+/// How many of the largest regions `--stats` lists as routines.
+const LARGEST_ROUTINES: usize = 10;
+
+/// Labels `vm_translator` generates as `NAME.n` inside a function: return
+/// addresses, comparison branches and the local-initialization loop.
+const GENERATED_LABELS: [&str; 7] = ["RETURN", "CONTINUE", "EQ", "GT", "LT", "WHILE1", "WHILE2"];
+/// Prefix of the return addresses of the translator `Pong.asm` came from.
+const RETURN_ADDRESS_PREFIX: &str = "RET_ADDRESS_";
+
+/// A routine: the words from a label up to the next label that does not
+/// belong to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    /// `None` for code before the first label.
+    pub label: Option<String>,
+    pub start: usize,
+    pub words: usize,
+}
+
+/// Instruction mix and ROM usage of an assembled program.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub words: usize,
+    pub a_instructions: usize,
+    pub c_instructions: usize,
+    pub variables: usize,
+    /// Uses of each mnemonic, with `null` for absent dest and jump fields.
+    pub comp: BTreeMap<&'static str, usize>,
+    pub dest: BTreeMap<&'static str, usize>,
+    pub jump: BTreeMap<&'static str, usize>,
+    /// In ROM order.
+    pub regions: Vec<Region>,
+}
+
+impl Stats {
+    /// Only labels that look like routine names start a new region. Labels
+    /// containing `$` (`function$label`), labels a VM translator generates
+    /// such as `RETURN.3` or `RET_ADDRESS_CALL7`, local labels of the current
+    /// region, labels named after it such as `LOOP_Main.main` and labels from
+    /// macro expansions stay in the routine around them.
+    pub fn new(program: &Program) -> Self {
+        let mut stats = Stats {
+            words: program.words.len(),
+            ..Stats::default()
+        };
+
+        for word in program.words.iter() {
+            match Instruction::decode(*word) {
+                Ok(Instruction::C { dest, comp, jump }) => {
+                    stats.c_instructions += 1;
+                    *stats.comp.entry(comp.mnemonic()).or_insert(0) += 1;
+                    *stats.dest.entry(dest.mnemonic()).or_insert(0) += 1;
+                    *stats.jump.entry(jump.mnemonic()).or_insert(0) += 1;
+                }
+                Ok(_) => stats.a_instructions += 1,
+                // illegal comp bits cannot come out of the assembler
+                Err(_) => stats.c_instructions += 1,
+            }
+        }
+
+        let mut labels: Vec<(usize, &str)> = vec![];
+        for definition in program.definitions.iter() {
+            match definition.kind {
+                DefinitionKind::Label => {
+                    labels.push((definition.address as usize, definition.name.as_str()))
+                }
+                DefinitionKind::Variable => stats.variables += 1,
+                DefinitionKind::Constant => {}
+            }
+        }
+        labels.sort_by_key(|(address, _)| *address);
+
+        let mut current = Region {
+            label: None,
+            start: 0,
+            words: 0,
+        };
+        for (address, label) in labels {
+            let internal = label.starts_with(MACRO_LABEL_PREFIX)
+                || label.contains('$')
+                || is_generated(label)
+                || matches!(&current.label, Some(scope) if label.starts_with(&format!("{}.", scope))
+                    || label.ends_with(&format!("_{}", scope)));
+            if internal {
+                continue;
+            }
+            current.words = address - current.start;
+            if current.label.is_some() || current.words > 0 {
+                stats.regions.push(current);
+            }
+            current = Region {
+                label: Some(label.to_string()),
+                start: address,
+                words: 0,
+            };
+        }
+        current.words = stats.words - current.start;
+        if current.label.is_some() || current.words > 0 {
+            stats.regions.push(current);
+        }
+
+        stats
+    }
+
+    /// The largest regions, largest first.
+    pub fn largest_routines(&self) -> Vec<&Region> {
+        let mut regions: Vec<&Region> = self.regions.iter().collect();
+        regions.sort_by(|a, b| b.words.cmp(&a.words).then(a.start.cmp(&b.start)));
+        regions.truncate(LARGEST_ROUTINES);
+        regions
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = format!(
+            "words {}\nA-instructions {}\nC-instructions {}\nvariables {}\n",
+            self.words, self.a_instructions, self.c_instructions, self.variables
+        );
+        for (title, counts) in [
+            ("comp", &self.comp),
+            ("dest", &self.dest),
+            ("jump", &self.jump),
+        ]
+        .iter()
+        {
+            text.push_str(&format!("\n# {}\n", title));
+            let mut counts: Vec<(&&str, &usize)> = counts.iter().collect();
+            counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            for (mnemonic, count) in counts {
+                text.push_str(&format!("{} {}\n", mnemonic, count));
+            }
+        }
+        text.push_str("\n# regions (ROM address, words)\n");
+        for region in self.regions.iter() {
+            text.push_str(&format!(
+                "{} {} {}\n",
+                region_name(region),
+                region.start,
+                region.words
+            ));
+        }
+        text.push_str("\n# largest routines\n");
+        for region in self.largest_routines() {
+            text.push_str(&format!("{} {}\n", region_name(region), region.words));
+        }
+        text
+    }
+
+    pub fn json(&self) -> String {
+        let counts = |counts: &BTreeMap<&str, usize>| {
+            let members: Vec<String> = counts
+                .iter()
+                .map(|(mnemonic, count)| format!("{}: {}", json_string(mnemonic), count))
+                .collect();
+            format!("{{{}}}", members.join(", "))
+        };
+        let regions = |regions: Vec<&Region>| {
+            let items: Vec<String> = regions
+                .iter()
+                .map(|region| {
+                    format!(
+                        "    {{\"label\": {}, \"start\": {}, \"words\": {}}}",
+                        region
+                            .label
+                            .as_ref()
+                            .map_or(String::from("null"), |label| json_string(label)),
+                        region.start,
+                        region.words
+                    )
+                })
+                .collect();
+            if items.is_empty() {
+                String::from("[]")
+            } else {
+                format!("[\n{}\n  ]", items.join(",\n"))
+            }
+        };
+
+        format!(
+            "{{\n  \"words\": {},\n  \"a_instructions\": {},\n  \"c_instructions\": {},\n  \"variables\": {},\n  \"comp\": {},\n  \"dest\": {},\n  \"jump\": {},\n  \"regions\": {},\n  \"largest_routines\": {}\n}}\n",
+            self.words,
+            self.a_instructions,
+            self.c_instructions,
+            self.variables,
+            counts(&self.comp),
+            counts(&self.dest),
+            counts(&self.jump),
+            regions(self.regions.iter().collect()),
+            regions(self.largest_routines())
+        )
+    }
+}
+
+fn is_generated(label: &str) -> bool {
+    let numbered = match label.rsplit_once('.') {
+        Some((name, number)) => {
+            GENERATED_LABELS.contains(&name)
+                && !number.is_empty()
+                && number.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    };
+    numbered || label.starts_with(RETURN_ADDRESS_PREFIX)
+}
+
+fn region_name(region: &Region) -> &str {
+    region.label.as_deref().unwrap_or("(start)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Region, Stats};
+    use crate::assemble;
+
+    #[test]
+    fn count_max() {
+        let program = assemble(include_str!("../../max/Max.asm")).unwrap();
+        let stats = Stats::new(&program);
+        assert_eq!((stats.a_instructions, stats.c_instructions), (8, 8));
+        assert_eq!(stats.comp["D"], 2);
+        assert_eq!(stats.dest["D"], 4);
+        assert_eq!(stats.jump["JMP"], 2);
+        assert_eq!(
+            stats.regions,
+            vec![
+                Region {
+                    label: None,
+                    start: 0,
+                    words: 10
+                },
+                Region {
+                    label: Some(String::from("OUTPUT_FIRST")),
+                    start: 10,
+                    words: 2
+                },
+                Region {
+                    label: Some(String::from("OUTPUT_D")),
+                    start: 12,
+                    words: 2
+                },
+                Region {
+                    label: Some(String::from("INFINITE_LOOP")),
+                    start: 14,
+                    words: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn keep_internal_labels_in_their_routine() {
+        let source =
+            "(f)\n@x\n(f$loop)\n(.inner)\n(RETURN.1)\n(LOOP_f)\n@f$loop\n0;JMP\n(Main.test1)\nD=0\n";
+        let stats = Stats::new(&assemble(source).unwrap());
+        assert_eq!(stats.variables, 1);
+        let routines: Vec<(Option<&str>, usize)> = stats
+            .largest_routines()
+            .iter()
+            .map(|region| (region.label.as_deref(), region.words))
+            .collect();
+        assert_eq!(routines, vec![(Some("f"), 3), (Some("Main.test1"), 1)]);
+        assert!(stats.json().contains(
+            "\"largest_routines\": [\n    {\"label\": \"f\", \"start\": 0, \"words\": 3},"
+        ));
+        assert!(stats
+            .text()
+            .contains("# largest routines\nf 3\nMain.test1 1\n"));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{symbol_table::PREDEFINED_SYMBOLS, Definition, DefinitionKind, Program};
+
+/// Plain text symbol map: one `name address` pair per line, grouped into
+/// predefined symbols, constants, labels (ROM addresses) and variables (RAM
+/// addresses).
+pub fn symbols_text(program: &Program) -> String {
+    let mut text = String::from("# predefined\n");
+    for (symbol, address) in PREDEFINED_SYMBOLS.iter() {
+        text.push_str(&format!("{} {}\n", symbol, address));
+    }
+
+    text.push_str("\n# constants\n");
+    for definition in sorted(program, DefinitionKind::Constant) {
+        text.push_str(&format!("{} {}\n", definition.name, definition.address));
+    }
+
+    text.push_str("\n# labels (ROM)\n");
+    for definition in sorted(program, DefinitionKind::Label) {
+        text.push_str(&format!("{} {}\n", definition.name, definition.address));
+    }
+
+    text.push_str("\n# variables (RAM)\n");
+    for definition in sorted(program, DefinitionKind::Variable) {
+        text.push_str(&format!("{} {}\n", definition.name, definition.address));
+    }
+
+    text
+}
+
+/// The same map as `symbols_text` as a JSON object with `predefined`,
+/// `constants`, `labels` and `variables` members mapping names to values.
+pub fn symbols_json(program: &Program) -> String {
+    let predefined = PREDEFINED_SYMBOLS
+        .iter()
+        .map(|(symbol, address)| format!("    {}: {}", json_string(symbol), address))
+        .collect::<Vec<String>>();
+    let constants = json_entries(program, DefinitionKind::Constant);
+    let labels = json_entries(program, DefinitionKind::Label);
+    let variables = json_entries(program, DefinitionKind::Variable);
+
+    format!(
+        "{{\n  \"predefined\": {},\n  \"constants\": {},\n  \"labels\": {},\n  \"variables\": {}\n}}\n",
+        json_object(&predefined),
+        json_object(&constants),
+        json_object(&labels),
+        json_object(&variables)
+    )
+}
+
+fn json_entries(program: &Program, kind: DefinitionKind) -> Vec<String> {
+    sorted(program, kind)
+        .iter()
+        .map(|definition| {
+            format!(
+                "    {}: {}",
+                json_string(&definition.name),
+                definition.address
+            )
+        })
+        .collect()
+}
+
+fn sorted(program: &Program, kind: DefinitionKind) -> Vec<&Definition> {
+    let mut definitions = program
+        .definitions
+        .iter()
+        .filter(|definition| definition.kind == kind)
+        .collect::<Vec<&Definition>>();
+    definitions.sort_by_key(|definition| definition.address);
+    definitions
+}
+
+fn json_object(members: &[String]) -> String {
+    if members.is_empty() {
+        String::from("{}")
+    } else {
+        format!("{{\n{}\n  }}", members.join(",\n"))
+    }
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{symbols_json, symbols_text};
+    use crate::assemble;
+
+    #[test]
+    fn separate_labels_and_variables() {
+        let program = assemble("(LOOP)\n@counter\nM=M+1\n@LOOP\n0;JMP\n").unwrap();
+
+        let text = symbols_text(&program);
+        assert!(text.contains("# labels (ROM)\nLOOP 0\n"));
+        assert!(text.contains("# variables (RAM)\ncounter 16\n"));
+
+        let json = symbols_json(&program);
+        assert!(json.contains("\"labels\": {\n    \"LOOP\": 0\n  }"));
+        assert!(json.contains("\"variables\": {\n    \"counter\": 16\n  }"));
+        assert!(json.contains("\"SCREEN\": 16384"));
+    }
+
+    #[test]
+    fn list_constants() {
+        let program = assemble(".equ SIZE 8192\n@SIZE\n").unwrap();
+        assert!(symbols_text(&program).contains("# constants\nSIZE 8192\n"));
+        assert!(symbols_json(&program).contains("\"constants\": {\n    \"SIZE\": 8192\n  }"));
+    }
+}
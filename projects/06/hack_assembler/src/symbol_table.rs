@@ -1,5 +1,34 @@
 use std::collections::HashMap;
 
+use crate::VARIABLE_BASE;
+
+/// Symbols every Hack program starts with, in the order the book lists them.
+pub const PREDEFINED_SYMBOLS: [(&str, u16); 23] = [
+    ("SP", 0),
+    ("LCL", 1),
+    ("ARG", 2),
+    ("THIS", 3),
+    ("THAT", 4),
+    ("R0", 0),
+    ("R1", 1),
+    ("R2", 2),
+    ("R3", 3),
+    ("R4", 4),
+    ("R5", 5),
+    ("R6", 6),
+    ("R7", 7),
+    ("R8", 8),
+    ("R9", 9),
+    ("R10", 10),
+    ("R11", 11),
+    ("R12", 12),
+    ("R13", 13),
+    ("R14", 14),
+    ("R15", 15),
+    ("SCREEN", 16384),
+    ("KBD", 24576),
+];
+
 #[derive(Debug)]
 pub struct SymbolTable {
     pub table: HashMap<String, u16>,
@@ -11,31 +40,11 @@ impl SymbolTable {
             table: HashMap::new(),
         };
 
-        symbol_table.add_entry("SP", 0);
-        symbol_table.add_entry("LCL", 1);
-        symbol_table.add_entry("ARG", 2);
-        symbol_table.add_entry("THIS", 3);
-        symbol_table.add_entry("THAT", 4);
-        symbol_table.add_entry("R0", 0);
-        symbol_table.add_entry("R1", 1);
-        symbol_table.add_entry("R2", 2);
-        symbol_table.add_entry("R3", 3);
-        symbol_table.add_entry("R4", 4);
-        symbol_table.add_entry("R5", 5);
-        symbol_table.add_entry("R6", 6);
-        symbol_table.add_entry("R7", 7);
-        symbol_table.add_entry("R8", 8);
-        symbol_table.add_entry("R9", 9);
-        symbol_table.add_entry("R10", 10);
-        symbol_table.add_entry("R11", 11);
-        symbol_table.add_entry("R12", 12);
-        symbol_table.add_entry("R13", 13);
-        symbol_table.add_entry("R14", 14);
-        symbol_table.add_entry("R15", 15);
-        symbol_table.add_entry("SCREEN", 16384);
-        symbol_table.add_entry("KBD", 24576);
-
-        return  symbol_table;
+        for (symbol, address) in PREDEFINED_SYMBOLS.iter() {
+            symbol_table.add_entry(symbol, *address);
+        }
+
+        symbol_table
     }
 
     pub fn add_entry(&mut self, symbol: &str, value: u16) {
@@ -49,11 +58,61 @@ impl SymbolTable {
     pub fn get_address(&self, name: &String) -> u16 {
         *self.table.get(name).unwrap()
     }
+
+    pub fn is_predefined(name: &str) -> bool {
+        PREDEFINED_SYMBOLS.iter().any(|(symbol, _)| *symbol == name)
+    }
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hands out RAM addresses to variables from `VARIABLE_BASE` upward, for
+/// both the assembler and the linker.
+#[derive(Debug)]
+pub(crate) struct VariableAllocator {
+    next: u32,
+    ceiling: u16,
+    overflowed: bool,
+}
+
+impl VariableAllocator {
+    pub(crate) fn new(ceiling: u16) -> Self {
+        VariableAllocator {
+            next: VARIABLE_BASE as u32,
+            ceiling,
+            overflowed: false,
+        }
+    }
+
+    /// The address of a new variable, and whether it is the first one above
+    /// the ceiling, so that only that one is reported. With a ceiling below
+    /// `VARIABLE_BASE` every variable is above it.
+    pub(crate) fn allocate(&mut self) -> (u16, bool) {
+        let address = self.next;
+        let first_above = address > self.ceiling as u32 && !self.overflowed;
+        self.overflowed |= first_above;
+        self.next += 1;
+        (address as u16, first_above)
+    }
+
+    /// Whether a variable was allocated above the ceiling.
+    pub(crate) fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Number of variables allocated so far.
+    pub(crate) fn count(&self) -> u32 {
+        self.next - VARIABLE_BASE as u32
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::SymbolTable;
+    use super::{SymbolTable, VariableAllocator};
 
     #[test]
     fn init_symbol() {
@@ -62,6 +121,12 @@ mod tests {
         assert!(!symbol_table.contains(&String::from("NOTCONTAIN")));
     }
 
+    #[test]
+    fn is_predefined() {
+        assert!(SymbolTable::is_predefined("KBD"));
+        assert!(!SymbolTable::is_predefined("LOOP"));
+    }
+
     #[test]
     fn add_entry() {
         let mut symbol_table = SymbolTable::new();
@@ -79,4 +144,17 @@ mod tests {
         assert_eq!(symbol_table.get_address(&String::from("NEW")), 20);
     }
 
+    #[test]
+    fn allocate_variables_up_to_the_ceiling() {
+        let mut allocator = VariableAllocator::new(17);
+        assert_eq!(allocator.allocate(), (16, false));
+        assert_eq!(allocator.allocate(), (17, false));
+        assert_eq!(allocator.allocate(), (18, true));
+        assert_eq!(allocator.allocate(), (19, false));
+        assert!(allocator.overflowed());
+        assert_eq!(allocator.count(), 4);
+
+        let mut allocator = VariableAllocator::new(10);
+        assert_eq!(allocator.allocate(), (16, true));
+    }
 }